@@ -1,119 +1,586 @@
-use super::model::Model;
-use std::collections::HashMap;
-
-/// Calculate the dot product of vec1 and vec2.
-pub fn dot_product(vec1: &[f64], vec2: &[f64]) -> f64 {
-    vec1.iter().zip(vec2).map(|(&x, &y)| x * y).sum()
-}
+use super::fields::Field;
+use super::model::{DocumentInfo, Model, Posting};
+use super::query::{self, PreparedQuery, QueryTerm};
+use super::scoring::{Factors, Scorer};
+use super::snippets::{document_snippets, Snippet};
+use super::text_process::Token;
+use super::vocabulary::{TermId, Vocabulary};
+use std::collections::{HashMap, HashSet};
 
 /// Determine the magnitude of a 1D vector.
 pub fn vector_magnitude(vec1: &[f64]) -> f64 {
     vec1.iter().map(|&x| x * x).sum::<f64>().sqrt()
 }
 
-/// Determine the cosine similarity between vec1 and vec2.
-pub fn cosine_similarity(vec1: &[f64], vec2: &[f64]) -> f64 {
-    let dot_prod: f64 = dot_product(vec1, vec2);
-
+/// Determine the cosine similarity of two vectors from their dot product and magnitudes.
+pub fn cosine_similarity(dot_prod: f64, mag_vec1: f64, mag_vec2: f64) -> f64 {
     if dot_prod == 0.0 {
         0.0
     } else {
-        let mag_vec1: f64 = vector_magnitude(vec1);
-        let mag_vec2: f64 = vector_magnitude(vec2);
         dot_prod / (mag_vec1 * mag_vec2)
     }
 }
 
-/// Generate TF values for each document.
-pub fn tf_calculation(corpus: &[Vec<String>], all_words: &HashMap<String, i32>) -> Vec<Vec<f64>> {
-    let mut tf_vals: Vec<Vec<f64>> = vec![vec![0.0; all_words.len()]; corpus.len()];
+/// Generate TF values and positions for each document, stored as a postings list for each term
+/// id.
+pub fn tf_calculation(corpus: &[Vec<Token>], vocabulary: &Vocabulary) -> Vec<Vec<Posting>> {
+    let mut postings: Vec<Vec<Posting>> = vec![Vec::new(); vocabulary.len()];
 
+    // Documents are visited in order, so each postings list stays sorted by document.
     for (i, item) in corpus.iter().enumerate() {
-        for (j, (word, _)) in all_words.iter().enumerate() {
-            tf_vals[i][j] = item
-                .iter()
-                .fold(0.0, |acc, x| if x == word { acc + 1.0 } else { acc });
+        for (id, posting) in document_postings(i, item, vocabulary) {
+            postings[id].push(posting);
         }
     }
 
-    tf_vals
+    postings
 }
 
-/// Perform an Inverse Document Frequency calculation.
-pub fn idf_calculation(corpus: &[Vec<String>], all_words: &HashMap<String, i32>) -> Vec<f64> {
-    let numerator: f64 = corpus.len() as f64;
+/// Generate the postings of one document, for each term id in it.
+pub fn document_postings(
+    doc: usize,
+    tokens: &[Token],
+    vocabulary: &Vocabulary,
+) -> HashMap<TermId, Posting> {
+    let mut postings: HashMap<TermId, Posting> = HashMap::new();
+
+    for token in tokens {
+        let Some(id) = vocabulary.id(&token.text) else {
+            continue;
+        };
+
+        let posting = postings.entry(id).or_insert_with(|| Posting {
+            doc,
+            tf: 0.0,
+            positions: Vec::new(),
+        });
+        posting.tf += 1.0;
+        posting.positions.push(token.position);
+    }
 
-    all_words
-        .iter()
-        .map(|(_, &x)| (numerator / (1.0 + f64::from(x))).ln())
+    postings
+}
+
+/// Inverse Document Frequency of a term occurring in `df` of `n_docs` documents, smoothed as
+/// `ln(1 + N / df)` so that a term in every document still counts.
+pub fn idf(n_docs: usize, df: u32) -> f64 {
+    match df {
+        0 => 0.0,
+        df => (1.0 + n_docs as f64 / f64::from(df)).ln(),
+    }
+}
+
+/// Perform an Inverse Document Frequency calculation for each term id.
+pub fn idf_calculation(n_docs: usize, vocabulary: &Vocabulary) -> Vec<f64> {
+    (0..vocabulary.len())
+        .map(|id| idf(n_docs, vocabulary.doc_freq(id)))
         .collect::<Vec<f64>>()
 }
 
-/// Perform the text-frequency inverse document frequency calculation.
-pub fn tf_idf_calculation(tf_vals: &[Vec<f64>], idf_vals: &[f64]) -> Vec<Vec<f64>> {
-    // Generate TF-IDF values.
-    let mut tf_idf: Vec<Vec<f64>> = vec![vec![0.0; tf_vals[0].len()]; tf_vals.len()];
+/// Calculate the magnitude of each document's TF-IDF vector from the postings. Each TF is
+/// boosted by the field of its term, as it is when a query word is matched in every field.
+pub fn document_norms(
+    postings: &[Vec<Posting>],
+    idf_vals: &[f64],
+    vocabulary: &Vocabulary,
+    n_docs: usize,
+) -> Vec<f64> {
+    let mut sums: Vec<f64> = vec![0.0; n_docs];
+
+    for (id, (list, &idf)) in postings.iter().zip(idf_vals).enumerate() {
+        let boost = Field::of_term(vocabulary.term(id)).boost();
+        for posting in list {
+            sums[posting.doc] += (boost * posting.tf * idf).powi(2);
+        }
+    }
 
-    for (c1, doc) in tf_vals.iter().enumerate() {
-        for (c2, word) in idf_vals.iter().enumerate() {
-            tf_idf[c1][c2] = doc[c2] * word;
+    sums.iter().map(|x| x.sqrt()).collect()
+}
+
+/// Calculate the mean length of the documents in tokens.
+pub fn average_length(documents: &[DocumentInfo]) -> f64 {
+    if documents.is_empty() {
+        return 0.0;
+    }
+
+    let total: f64 = documents.iter().map(|doc| f64::from(doc.length)).sum();
+    total / documents.len() as f64
+}
+
+/// Length of the shortest run of positions containing at least one position from each of the
+/// (ascending) lists, or `None` if any list is empty.
+pub fn min_span(positions: &[&[u32]]) -> Option<u32> {
+    if positions.iter().any(|list| list.is_empty()) {
+        return None;
+    }
+
+    let mut next: Vec<usize> = vec![0; positions.len()];
+    let mut best: Option<u32> = None;
+
+    loop {
+        let current = positions.iter().zip(&next).map(|(list, &i)| list[i]);
+        let (lowest, low) = current.clone().enumerate().min_by_key(|&(_, p)| p)?;
+        let high = current.max()?;
+
+        let span = high - low + 1;
+        best = Some(best.map_or(span, |best| best.min(span)));
+
+        // Only moving past the lowest position can give a shorter window.
+        next[lowest] += 1;
+        if next[lowest] == positions[lowest].len() {
+            return best;
         }
     }
+}
 
-    tf_idf
+/// Rank scores in descending order, removing entries with a score of zero or which are not
+/// finite.
+///
+/// Scores may be negative (e.g. log probabilities), so only zero marks a document with nothing in
+/// common with the query.
+fn rank(scores: Vec<(usize, f64)>) -> Vec<(usize, f64)> {
+    let mut ranked: Vec<(usize, f64)> = scores
+        .into_iter()
+        .filter(|&(_, s)| s != 0.0 && s.is_finite())
+        .collect();
+
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    ranked
 }
 
-/// Rank cosine similarities by index, remove entries with zero similarity.
-fn rank_idx(cosine_similarities: &[f64]) -> Vec<usize> {
-    let mut indices: Vec<usize> = (0..cosine_similarities.len()).collect::<Vec<usize>>();
+/// Score the documents matching a query, best first, or in the order of its sort key.
+pub fn rank_documents(
+    query: &PreparedQuery,
+    model: &Model,
+    scorer: &dyn Scorer,
+) -> Vec<(usize, f64)> {
+    let mut ranked: Vec<(usize, f64)> = if query.terms.is_empty() {
+        // A query of only filters or a sort key has nothing to score, so every document it
+        // matches is kept.
+        query.documents.iter().map(|&doc| (doc, 0.0)).collect()
+    } else {
+        score_documents(query, model, scorer)
+    };
 
-    indices.sort_by(|&a, &b| {
-        cosine_similarities[b]
-            .partial_cmp(&cosine_similarities[a])
-            .expect("ranking failed")
-    });
+    // The sort is stable, so documents with the same value stay in order of score.
+    if let Some(sort) = &query.sort {
+        ranked.sort_by(|a, b| {
+            sort.compare(
+                &model.documents[a.0].metadata,
+                &model.documents[b.0].metadata,
+            )
+        });
+    }
 
-    indices
+    ranked
+}
+
+fn score_documents(query: &PreparedQuery, model: &Model, scorer: &dyn Scorer) -> Vec<(usize, f64)> {
+    let scores: Vec<(usize, f64)> = query
+        .documents
         .iter()
-        .filter(|&&x| cosine_similarities[x] > 0.0)
-        .copied()
+        .map(|&doc| {
+            let score: f64 = term_scores(query, model, scorer, doc)
+                .map(|(_, _, score)| score)
+                .sum();
+            (doc, scorer.normalise(model, &query.terms, doc, score))
+        })
+        .collect();
+
+    rank(scores)
+}
+
+/// Each term of a query with its frequency in a document and what it adds to the score of the
+/// document, before the sum is normalised. Used both to rank and to explain scores, so that the
+/// two always agree.
+fn term_scores<'a>(
+    query: &'a PreparedQuery,
+    model: &'a Model,
+    scorer: &'a dyn Scorer,
+    doc: usize,
+) -> impl Iterator<Item = (&'a QueryTerm, f64, f64)> + 'a {
+    query.terms.iter().map(move |term| {
+        let tf: f64 = term.tf(model, doc, scorer.boosts_fields());
+        (
+            term,
+            tf,
+            term.weight * scorer.term_score(model, &term.stats, tf, doc),
+        )
+    })
+}
+
+/// How a term of a query adds to the score of a document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TermExplanation {
+    pub text: String,
+    /// Number of times the term appears in the query.
+    pub weight: f64,
+    /// Frequency of the term in the document, the sum of its occurrences in `sources`.
+    pub tf: f64,
+    /// The terms of the vocabulary found in the document (e.g. `title:ownership`), each with
+    /// its number of occurrences and what each counts for, such as the boost of the field.
+    pub sources: Vec<(String, f64, f64)>,
+    /// The values the ranking function worked the score out from (see `Scorer::explain_term`).
+    pub factors: Factors,
+    /// What the term adds to the score, times its weight.
+    pub score: f64,
+}
+
+/// How the score of a document for a query is made up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explanation {
+    pub terms: Vec<TermExplanation>,
+    /// The sum of the term scores.
+    pub sum: f64,
+    /// The values the ranking function normalised the sum with (see
+    /// `Scorer::explain_normalise`), including any proximity boost.
+    pub factors: Factors,
+    /// The score the document is ranked by.
+    pub score: f64,
+}
+
+impl std::fmt::Display for Explanation {
+    /// A line for each term and one for the normalisation, e.g.
+    /// `    borrow: 0.41 (tf 5 = borrow 2 + title:borrow 1 × 3; idf 1.1)`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let factors = |factors: &Factors| {
+            factors
+                .iter()
+                .map(|(name, value)| format!("{name} {}", short_number(*value)))
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+
+        for term in &self.terms {
+            write!(f, "    {}: {:.4} (", term.text, term.score)?;
+            if term.weight != 1.0 {
+                write!(f, "weight {}, ", short_number(term.weight))?;
+            }
+            write!(f, "tf {}", short_number(term.tf))?;
+            // The sources are only worth listing if there is more to them than the term itself.
+            if term.sources.len() > 1 || term.sources.iter().any(|source| source.2 != 1.0) {
+                let sources: Vec<String> = term
+                    .sources
+                    .iter()
+                    .map(|(source, tf, weight)| {
+                        if *weight == 1.0 {
+                            format!("{source} {}", short_number(*tf))
+                        } else {
+                            let (tf, weight) = (short_number(*tf), short_number(*weight));
+                            format!("{source} {tf} × {weight}")
+                        }
+                    })
+                    .collect();
+                write!(f, " = {}", sources.join(" + "))?;
+            }
+            if !term.factors.is_empty() {
+                write!(f, "; {}", factors(&term.factors))?;
+            }
+            writeln!(f, ")")?;
+        }
+
+        write!(f, "    sum {:.4}", self.sum)?;
+        if !self.factors.is_empty() {
+            write!(f, " ({})", factors(&self.factors))?;
+        }
+        writeln!(f, " → {:.4}", self.score)
+    }
+}
+
+/// A number with at most four decimal places and no trailing zeros.
+fn short_number(number: f64) -> String {
+    let text = format!("{number:.4}");
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// Break the score of a document for a query down into what each term adds and how the sum is
+/// normalised. The score is worked out the same way as when ranking.
+pub fn explain_document(
+    query: &PreparedQuery,
+    model: &Model,
+    scorer: &dyn Scorer,
+    doc: usize,
+) -> Explanation {
+    let terms: Vec<TermExplanation> = term_scores(query, model, scorer, doc)
+        .map(|(term, tf, score)| TermExplanation {
+            text: term.text.clone(),
+            weight: term.weight,
+            tf,
+            sources: term
+                .sources
+                .iter()
+                .filter_map(|&(id, weight, boost)| {
+                    let posting = query::find_posting(&model.postings[id], doc)?;
+                    let weight = if scorer.boosts_fields() {
+                        weight * boost
+                    } else {
+                        weight
+                    };
+                    Some((model.vocabulary.term(id).to_string(), posting.tf, weight))
+                })
+                .collect(),
+            factors: scorer.explain_term(model, &term.stats, tf, doc),
+            score,
+        })
+        .collect();
+    let sum: f64 = terms.iter().map(|term| term.score).sum();
+
+    Explanation {
+        factors: scorer.explain_normalise(model, &query.terms, doc, sum),
+        score: scorer.normalise(model, &query.terms, doc, sum),
+        terms,
+        sum,
+    }
+}
+
+/// Keep only the first ranked passage of each file, so that files are ranked by their best
+/// passage. Documents which are whole files are kept as they are.
+pub fn best_passages(ranking: Vec<(usize, f64)>, model: &Model) -> Vec<(usize, f64)> {
+    let mut seen: HashSet<&str> = HashSet::new();
+    ranking
+        .into_iter()
+        .filter(|&(doc, _)| seen.insert(&model.documents[doc].path))
         .collect()
 }
 
-pub fn score_query(test_vec: &[f64], model: &Model, path_list: &[String], top_n: usize) {
-    let cos_sim: Vec<f64> = model
-        .tf_idf
-        .iter()
-        .map(|x| cosine_similarity(test_vec, x))
-        .collect::<Vec<f64>>();
+/// How results are written out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// Plain text.
+    #[default]
+    Plain,
+    /// Text with the words of snippets highlighted in ANSI colour, for a terminal.
+    Colour,
+    /// A line of JSON for each query, with the words of snippets between `<mark>` and `</mark>`.
+    Json,
+}
 
-    let ranking_idx = rank_idx(&cos_sim);
+/// How the results of a query are shown.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResultOptions {
+    /// Most results to show.
+    pub top_n: usize,
+    /// Whether to show each file once, at its best passage, when files are split into passages.
+    pub by_document: bool,
+    /// Most snippets to show for each result.
+    pub snippets: usize,
+    pub format: OutputFormat,
+    /// Whether to show how the score of each result is made up (see `explain_document`).
+    pub explain: bool,
+}
 
-    let top = if ranking_idx.len() < top_n {
-        ranking_idx.len()
-    } else {
-        top_n
-    };
+impl Default for ResultOptions {
+    fn default() -> Self {
+        Self {
+            top_n: 10,
+            by_document: false,
+            snippets: 2,
+            format: OutputFormat::Plain,
+            explain: false,
+        }
+    }
+}
+
+pub fn score_query(
+    query: &PreparedQuery,
+    model: &Model,
+    scorer: &dyn Scorer,
+    options: &ResultOptions,
+) {
+    let mut ranking = rank_documents(query, model, scorer);
+    if options.by_document {
+        ranking = best_passages(ranking, model);
+    }
+    let results = ranking.iter().take(options.top_n).map(|&(i, score)| {
+        let document = &model.documents[i];
+        let snippets = document_snippets(document, &query.words, &model.analyzer, options.snippets);
+        // A query of only filters or a sort key has no score to explain.
+        let explanation = (options.explain && !query.terms.is_empty())
+            .then(|| explain_document(query, model, scorer, i));
+        (document, score, snippets, explanation)
+    });
+
+    if options.format == OutputFormat::Json {
+        let results: Vec<String> = results
+            .map(|(document, score, snippets, explanation)| {
+                json_result(document, score, &snippets, explanation.as_ref())
+            })
+            .collect();
+        println!("[{}]", results.join(","));
+        return;
+    }
 
     println!("\nResults:\n");
-    for &i in &ranking_idx[..top] {
-        println!("{}, ({:.2})", path_list[i], cos_sim[i]);
+    for (document, score, snippets, explanation) in results {
+        match query.sort.as_ref().and_then(|sort| {
+            let value = document.metadata.get(&sort.key)?;
+            Some(format!("{}: {value}", sort.key))
+        }) {
+            Some(sorted_by) => println!("{}, ({score:.2}) [{sorted_by}]", document.location()),
+            None => println!("{}, ({:.2})", document.location(), score),
+        }
+        for snippet in snippets {
+            let text = match options.format {
+                OutputFormat::Colour => snippet.marked("\x1b[1;31m", "\x1b[0m"),
+                _ => snippet.text,
+            };
+            println!("    {}: {text}", snippet.line);
+        }
+        if let Some(explanation) = explanation {
+            print!("{explanation}");
+        }
     }
 
     println!("------");
 }
 
+/// A result as a JSON object.
+fn json_result(
+    document: &DocumentInfo,
+    score: f64,
+    snippets: &[Snippet],
+    explanation: Option<&Explanation>,
+) -> String {
+    let passage = document.passage.as_ref();
+    let snippets: Vec<String> = snippets
+        .iter()
+        .map(|snippet| {
+            format!(
+                "{{\"line\":{},\"text\":{}}}",
+                snippet.line,
+                json_string(&snippet.marked("<mark>", "</mark>"))
+            )
+        })
+        .collect();
+
+    format!(
+        "{{\"path\":{},\"line\":{},\"section\":{},\"score\":{},\"snippets\":[{}]{}}}",
+        json_string(&document.path),
+        passage.map_or(String::from("null"), |passage| passage
+            .start_line
+            .to_string()),
+        passage
+            .and_then(|passage| passage.section.as_deref())
+            .map_or(String::from("null"), json_string),
+        json_number(score),
+        snippets.join(","),
+        explanation.map_or(String::new(), |explanation| format!(
+            ",\"explanation\":{}",
+            json_explanation(explanation)
+        ))
+    )
+}
+
+/// An explanation as a JSON object, with its factors as objects of their names and values.
+fn json_explanation(explanation: &Explanation) -> String {
+    let factors = |factors: &Factors| {
+        let factors: Vec<String> = factors
+            .iter()
+            .map(|(name, value)| format!("{}:{}", json_string(name), json_number(*value)))
+            .collect();
+        format!("{{{}}}", factors.join(","))
+    };
+    let terms: Vec<String> = explanation
+        .terms
+        .iter()
+        .map(|term| {
+            let sources: Vec<String> = term
+                .sources
+                .iter()
+                .map(|(source, tf, weight)| {
+                    format!(
+                        "{{\"term\":{},\"tf\":{},\"weight\":{}}}",
+                        json_string(source),
+                        json_number(*tf),
+                        json_number(*weight)
+                    )
+                })
+                .collect();
+            format!(
+                "{{\"text\":{},\"weight\":{},\"tf\":{},\"sources\":[{}],\"factors\":{},\"score\":{}}}",
+                json_string(&term.text),
+                json_number(term.weight),
+                json_number(term.tf),
+                sources.join(","),
+                factors(&term.factors),
+                json_number(term.score)
+            )
+        })
+        .collect();
+
+    format!(
+        "{{\"terms\":[{}],\"sum\":{},\"factors\":{},\"score\":{}}}",
+        terms.join(","),
+        json_number(explanation.sum),
+        factors(&explanation.factors),
+        json_number(explanation.score)
+    )
+}
+
+/// A number as JSON, which has no infinities or NaN.
+fn json_number(number: f64) -> String {
+    if number.is_finite() {
+        number.to_string()
+    } else {
+        String::from("null")
+    }
+}
+
+/// A string as a JSON string literal.
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::document::Document;
+    use crate::metadata::SortKey;
+    use crate::query::QueryTerm;
+    use crate::scoring::{self, Bm25, CosineTfIdf};
+    use crate::text_process::tokens_from_words;
+
+    fn test_corpus() -> Vec<Vec<Token>> {
+        [
+            vec![
+                "this".to_string(),
+                "is".to_string(),
+                "some".to_string(),
+                "text".to_string(),
+            ],
+            vec![
+                "and".to_string(),
+                "there".to_string(),
+                "is".to_string(),
+                "more".to_string(),
+            ],
+            vec!["this".to_string(), "too".to_string()],
+        ]
+        .iter()
+        .map(|words| tokens_from_words(words))
+        .collect()
+    }
 
-    #[test]
-    fn test_dot_product() {
-        let vector_a: Vec<f64> = vec![1.0, 2.0, 3.0];
-        let vector_b: Vec<f64> = vec![1.0, 2.0, 3.0];
-        let result = dot_product(&vector_a, &vector_b);
-        // note 16 decimal places for f64
-        assert_eq!(result, 14.0000000000000000);
+    fn posting(doc: usize, positions: Vec<u32>) -> Posting {
+        Posting {
+            doc,
+            tf: positions.len() as f64,
+            positions,
+        }
     }
 
     #[test]
@@ -126,115 +593,309 @@ mod tests {
 
     #[test]
     fn test_cosine_similarity_same() {
-        let vector_a: Vec<f64> = vec![1.0, 2.0, 3.0];
-        let vector_b: Vec<f64> = vec![1.0, 2.0, 3.0];
-        let result: f64 = cosine_similarity(&vector_a, &vector_b);
+        let result: f64 = cosine_similarity(14.0, 3.7416573867739413, 3.7416573867739413);
         assert_eq!(result, 1.0000000000000000);
     }
 
     #[test]
     fn test_cosine_similarity_orthogonal() {
-        let vector_a: Vec<f64> = vec![4.0, 2.0, 3.0];
-        let vector_b: Vec<f64> = vec![2.0, -4.0, 0.0];
-        let result: f64 = cosine_similarity(&vector_a, &vector_b);
+        let result: f64 = cosine_similarity(0.0, 5.385164807134504, 4.47213595499958);
         assert_eq!(result, 0.0000000000000000);
     }
 
     #[test]
     fn test_tf_calculation() {
-        let corpus: Vec<Vec<String>> = vec![
-            vec![
-                "this".to_string(),
-                "is".to_string(),
-                "some".to_string(),
-                "text".to_string(),
-            ],
-            vec![
-                "and".to_string(),
-                "there".to_string(),
-                "is".to_string(),
-                "more".to_string(),
-            ],
-            vec!["this".to_string(), "too".to_string()],
+        let corpus = test_corpus();
+        let vocabulary = Vocabulary::from_corpus(&corpus);
+
+        let result = tf_calculation(&corpus, &vocabulary);
+
+        assert_eq!(
+            result[vocabulary.id("is").unwrap()],
+            vec![posting(0, vec![1]), posting(1, vec![2])]
+        );
+        assert_eq!(
+            result[vocabulary.id("too").unwrap()],
+            vec![posting(2, vec![1])]
+        );
+        assert_eq!(result.len(), 8);
+    }
+
+    #[test]
+    fn test_idf_calculation() {
+        let corpus = test_corpus();
+        let vocabulary = Vocabulary::from_corpus(&corpus);
+
+        let result = idf_calculation(corpus.len(), &vocabulary);
+
+        // "is" occurs in two of the three documents, "too" in one.
+        assert_eq!(result[vocabulary.id("is").unwrap()], 2.5f64.ln());
+        assert_eq!(result[vocabulary.id("too").unwrap()], 4.0f64.ln());
+    }
+
+    #[test]
+    fn test_idf_uses_document_frequency() {
+        // A term repeated within one document is no more common across documents.
+        let corpus: Vec<Vec<Token>> = vec![
+            tokens_from_words(&["sky".to_string(), "sky".to_string(), "sky".to_string()]),
+            tokens_from_words(&["sun".to_string()]),
         ];
+        let vocabulary = Vocabulary::from_corpus(&corpus);
 
-        let mut words: HashMap<String, i32> = HashMap::new();
-        words.insert("this".to_string(), 2);
-        words.insert("is".to_string(), 2);
-        words.insert("some".to_string(), 1);
-        words.insert("text".to_string(), 1);
-        words.insert("and".to_string(), 1);
-        words.insert("there".to_string(), 1);
-        words.insert("more".to_string(), 1);
-        words.insert("too".to_string(), 1);
+        let result = idf_calculation(corpus.len(), &vocabulary);
 
-        let result = tf_calculation(&corpus, &words);
+        assert_eq!(result, vec![3.0f64.ln(), 3.0f64.ln()]);
+    }
 
-        // Collect line sums (result is irreproducible in inner vector ordering, stemming from
-        // irreproducible HashMap iteration in tf_calculation())
+    #[test]
+    fn test_document_norms() {
+        let vocabulary = Vocabulary::from_corpus(&[vec!["sky", "blue", "title:sky", "ext:md"]]);
+        let postings: Vec<Vec<Posting>> = vec![
+            vec![posting(0, vec![0, 1, 2])],
+            vec![posting(0, vec![3, 4, 5, 6])],
+            vec![posting(1, vec![0])],
+            vec![posting(1, vec![1])],
+        ];
+        let idf_vals: Vec<f64> = vec![1.0, 1.0, 1.0, 1.0];
+
+        let result: Vec<f64> = document_norms(&postings, &idf_vals, &vocabulary, 2);
+
+        // A word of the title counts three times, and the extension not at all.
+        assert_eq!(result, vec![5.0, 3.0]);
+    }
 
-        let test = result
+    #[test]
+    fn test_average_length() {
+        let documents: Vec<DocumentInfo> = [2, 4, 9]
             .iter()
-            .map(|line| line.iter().sum())
-            .collect::<Vec<f64>>();
+            .map(|&length| DocumentInfo {
+                length,
+                ..Default::default()
+            })
+            .collect();
+
+        assert_eq!(average_length(&documents), 5.0);
+        assert_eq!(average_length(&[]), 0.0);
+    }
+
+    #[test]
+    fn test_rank() {
+        let values = [1.0, 3.0, 2.0, 6.0, 5.0, 0.0, 0.0, 0.0];
+
+        let result = rank(values.iter().copied().enumerate().collect());
 
         assert_eq!(
-            test,
-            vec![4.0000000000000000, 4.0000000000000000, 2.0000000000000000]
+            result,
+            vec![(3, 6.0), (4, 5.0), (1, 3.0), (2, 2.0), (0, 1.0)]
         );
     }
 
     #[test]
-    fn test_idf_calculation() {
-        let corpus: Vec<Vec<String>> = vec![
-            vec![
-                "this".to_string(),
-                "is".to_string(),
-                "some".to_string(),
-                "text".to_string(),
-            ],
-            vec![
-                "and".to_string(),
-                "there".to_string(),
-                "is".to_string(),
-                "more".to_string(),
-            ],
-            vec!["this".to_string(), "too".to_string()],
-        ];
+    fn test_rank_not_finite() {
+        let values = [-2.0, f64::NAN, f64::NEG_INFINITY, -1.0, f64::INFINITY];
 
-        let mut words: HashMap<String, i32> = HashMap::new();
-        words.insert("this".to_string(), 2);
-        words.insert("is".to_string(), 2);
-        words.insert("some".to_string(), 1);
-        words.insert("text".to_string(), 1);
-        words.insert("and".to_string(), 1);
-        words.insert("there".to_string(), 1);
-        words.insert("more".to_string(), 1);
-        words.insert("too".to_string(), 1);
+        let result = rank(values.iter().copied().enumerate().collect());
 
-        let result = idf_calculation(&corpus, &words);
+        assert_eq!(result, vec![(3, -1.0), (0, -2.0)]);
+    }
+
+    #[test]
+    fn test_min_span() {
+        let result = min_span(&[&[1, 20, 40], &[9, 38], &[30, 44]]);
+        assert_eq!(result, Some(7));
 
-        let test: f64 = result.iter().sum();
+        let result = min_span(&[&[5], &[5]]);
+        assert_eq!(result, Some(1));
 
-        assert_eq!(test, 2.4327906486489868);
+        let result = min_span(&[&[1, 2], &[]]);
+        assert_eq!(result, None);
     }
 
     #[test]
-    fn test_tf_idf_calculation() {
-        let tf_vals: Vec<Vec<f64>> = vec![vec![1.0, 1.0, 1.0]];
-        let idf_vals: Vec<f64> = vec![1.0];
+    fn test_rank_documents() {
+        let corpus = test_corpus();
+        let documents: Vec<DocumentInfo> = corpus
+            .iter()
+            .enumerate()
+            .map(|(i, doc)| DocumentInfo {
+                path: format!("doc{i}.txt"),
+                length: doc.len() as u32,
+                ..Default::default()
+            })
+            .collect();
+        let model = Model::new(documents, &corpus);
+
+        let too = QueryTerm::from_term(&model, model.vocabulary.id("too").unwrap());
+        let query = PreparedQuery::from_terms(vec![too.clone()]);
+
+        let result = rank_documents(&query, &model, &CosineTfIdf);
 
-        let result: Vec<Vec<f64>> = tf_idf_calculation(&tf_vals, &idf_vals);
+        // Only the document containing the query term is scored.
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].0, 2);
 
-        assert_eq!(result, vec![vec![1.0, 0.0, 0.0]]);
+        let text = QueryTerm::from_term(&model, model.vocabulary.id("text").unwrap());
+        let query = PreparedQuery::from_terms(vec![too, text]);
+
+        let result = rank_documents(&query, &model, &Bm25::default());
+
+        // The shorter document ranks first for an equally rare term.
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].0, 2);
+
+        // Only the documents matched by the query are scored.
+        let mut query = PreparedQuery::from_terms(vec![QueryTerm::from_term(&model, 0)]);
+        query.documents.remove(&2);
+
+        let result = rank_documents(&query, &model, &Bm25::default());
+
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].0, 0);
     }
 
     #[test]
-    fn test_rank_idx() {
-        let values = [1.0, 3.0, 2.0, 6.0, 5.0, 0.0, 0.0, 0.0];
+    fn test_rank_single_document() {
+        let corpus: Vec<Vec<Token>> = vec![tokens_from_words(
+            &["the", "sky", "is", "blue"].map(String::from),
+        )];
+        let documents: Vec<DocumentInfo> = vec![DocumentInfo {
+            length: 4,
+            ..Default::default()
+        }];
+        let model = Model::new(documents, &corpus);
+
+        // A term in every document still has some weight, so the document is ranked.
+        let sky = QueryTerm::from_term(&model, model.vocabulary.id("sky").unwrap());
+        let query = PreparedQuery::from_terms(vec![sky]);
+        let result = rank_documents(&query, &model, &CosineTfIdf);
+
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].0, 0);
+        assert!(result[0].1 > 0.0);
+    }
+
+    #[test]
+    fn test_rank_documents_sorted() {
+        let corpus = test_corpus();
+        let front_matter = [
+            "---\ndate: 2025-03-01\n---\n",
+            "",
+            "---\ndate: 2024-12-31\n---\n",
+        ];
+        let documents: Vec<DocumentInfo> = corpus
+            .iter()
+            .zip(front_matter)
+            .map(|(doc, front_matter)| DocumentInfo {
+                length: doc.len() as u32,
+                metadata: Document::parse(front_matter),
+                ..Default::default()
+            })
+            .collect();
+        let model = Model::new(documents, &corpus);
+
+        // With nothing to score, every matching document is kept; undated documents come last.
+        let query = PreparedQuery {
+            terms: Vec::new(),
+            documents: (0..corpus.len()).collect(),
+            sort: Some(SortKey::new("date")),
+            words: Default::default(),
+        };
+
+        let result = rank_documents(&query, &model, &Bm25::default());
+
+        assert_eq!(result, vec![(2, 0.0), (0, 0.0), (1, 0.0)]);
+
+        // The shorter document would rank first by score.
+        let this = QueryTerm::from_term(&model, model.vocabulary.id("this").unwrap());
+        let mut query = PreparedQuery::from_terms(vec![this]);
+        query.sort = Some(SortKey::new("-date"));
+
+        let result = rank_documents(&query, &model, &Bm25::default());
+        let docs: Vec<usize> = result.iter().map(|&(doc, _)| doc).collect();
+
+        assert_eq!(docs, vec![0, 2]);
+    }
+
+    #[test]
+    fn test_explain_document() {
+        let corpus: Vec<Vec<Token>> = [
+            vec!["title:sky", "sky", "blue", "sky", "sun"],
+            vec!["sun", "bright", "blue"],
+            vec!["sky", "grey", "cloud", "rain", "wind", "cold"],
+        ]
+        .iter()
+        .map(|words| {
+            tokens_from_words(
+                &words
+                    .iter()
+                    .map(|word| word.to_string())
+                    .collect::<Vec<String>>(),
+            )
+        })
+        .collect();
+        let documents: Vec<DocumentInfo> = corpus
+            .iter()
+            .map(|doc| DocumentInfo {
+                length: doc.len() as u32,
+                ..Default::default()
+            })
+            .collect();
+        let model = Model::new(documents, &corpus);
+        let query =
+            query::parse_query("sky blue sky", &model, &query::QueryOptions::default()).unwrap();
+
+        // Explanations add up to the scores the documents are ranked by, whatever the ranker.
+        for name in scoring::SCORER_NAMES {
+            let scorer = scoring::scorer_from_name(name, &Default::default()).unwrap();
+            for (doc, score) in rank_documents(&query, &model, scorer.as_ref()) {
+                let explanation = explain_document(&query, &model, scorer.as_ref(), doc);
+                assert_eq!(explanation.score, score, "{name}");
+            }
+        }
+
+        let explanation = explain_document(&query, &model, &Bm25::default(), 0);
+        let sky = &explanation.terms[0];
+        assert_eq!(sky.weight, 2.0);
+        // Two occurrences in the body and one in the title, which counts three times.
+        assert_eq!(sky.tf, 5.0);
+        assert_eq!(
+            sky.sources,
+            vec![
+                (String::from("sky"), 2.0, 1.0),
+                (String::from("title:sky"), 1.0, 3.0)
+            ]
+        );
+        assert_eq!(
+            sky.factors
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>(),
+            vec!["idf", "length ratio", "saturated tf"]
+        );
+        assert_eq!(explanation.sum, sky.score + explanation.terms[1].score);
+        assert_eq!(
+            explanation.to_string(),
+            "    sky: 1.6506 (weight 2, tf 5 = sky 2 + title:sky 1 × 3; idf 0.47, length ratio 1.0714, \
+            saturated tf 1.756)\n    blue: 0.4567 (tf 1; idf 0.47, length ratio 1.0714, saturated tf \
+            0.9716)\n    sum 2.1073 → 2.1073\n"
+        );
+    }
 
-        let result = rank_idx(&values);
+    #[test]
+    fn test_json_result() {
+        let document = DocumentInfo {
+            path: String::from("notes/\"quoted\".md"),
+            ..Default::default()
+        };
+        let snippet = Snippet {
+            line: 3,
+            text: String::from("a \\ b\tc"),
+            highlights: std::iter::once(0..1).collect(),
+        };
 
-        assert_eq!(result, vec![3, 4, 1, 2, 0]);
+        assert_eq!(
+            json_result(&document, 0.5, &[snippet], None),
+            r#"{"path":"notes/\"quoted\".md","line":null,"section":null,"score":0.5,"snippets":[{"line":3,"text":"<mark>a</mark> \\ b\tc"}]}"#
+        );
     }
 }
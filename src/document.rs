@@ -0,0 +1,401 @@
+use pulldown_cmark::{
+    CodeBlockKind, Event, HeadingLevel, MetadataBlockKind, Options, Parser, Tag, TagEnd,
+};
+use std::fmt;
+
+/// A heading of a markdown document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heading {
+    /// 1 for `#`, up to 6 for `######`.
+    pub level: u8,
+    pub text: String,
+}
+
+/// A calendar date, as written `YYYY-MM-DD`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl Date {
+    /// Read a date written `YYYY-MM-DD`, ignoring a time after it (`2025-01-31T09:30:00`).
+    pub fn parse(text: &str) -> Option<Date> {
+        let (date, time) = (text.get(..10)?, &text[10..]);
+        if !(time.is_empty() || time.starts_with(['T', ' '])) {
+            return None;
+        }
+
+        let parts: Vec<&str> = date.split('-').collect();
+        let [year, month, day] = parts.as_slice() else {
+            return None;
+        };
+        if [year.len(), month.len(), day.len()] != [4, 2, 2]
+            || !date.chars().all(|c| c.is_ascii_digit() || c == '-')
+        {
+            return None;
+        }
+
+        let date = Date {
+            year: year.parse().ok()?,
+            month: month.parse().ok()?,
+            day: day.parse().ok()?,
+        };
+        ((1..=12).contains(&date.month) && (1..=31).contains(&date.day)).then_some(date)
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// A value of the front matter: a date, a number, other text, or a list of pieces of text.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Text(String),
+    Number(f64),
+    Date(Date),
+    List(Vec<String>),
+}
+
+impl Value {
+    /// The value a piece of text stands for: a date if it is written as one, otherwise a number
+    /// if it is one, otherwise the text itself.
+    pub fn typed(text: &str) -> Value {
+        if let Some(date) = Date::parse(text) {
+            return Value::Date(date);
+        }
+        match text.parse::<f64>() {
+            Ok(number) if number.is_finite() => Value::Number(number),
+            _ => Value::Text(text.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Text(text) => write!(f, "{text}"),
+            Value::Number(number) => write!(f, "{number}"),
+            Value::Date(date) => write!(f, "{date}"),
+            Value::List(items) => write!(f, "{}", items.join(", ")),
+        }
+    }
+}
+
+/// The structure of a markdown document, kept in the document table alongside its tokens so that
+/// ranking, filtering and display can use it.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Document {
+    /// The `title` of the front matter, otherwise the first heading.
+    pub title: Option<String>,
+    pub headings: Vec<Heading>,
+    /// The destinations of links.
+    pub links: Vec<String>,
+    /// The alt text of images.
+    pub images: Vec<String>,
+    /// The languages of fenced code blocks, where given.
+    pub code_languages: Vec<String>,
+    /// The keys and values of the YAML (`---`) or TOML (`+++`) front matter, in order, with
+    /// dates and numbers read as such.
+    pub front_matter: Vec<(String, Value)>,
+}
+
+impl Document {
+    /// Parse the structure of a markdown document (a `.txt` file is read the same way).
+    pub fn parse(contents: &str) -> Self {
+        let options = Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
+            | Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS;
+
+        let mut document = Document::default();
+        // The text of the heading, image or front matter being read, if any.
+        let mut heading: Option<String> = None;
+        let mut alt: Option<String> = None;
+        let mut metadata: Option<String> = None;
+
+        for event in Parser::new_ext(contents, options) {
+            match event {
+                Event::Start(Tag::Heading { .. }) => heading = Some(String::new()),
+                Event::Start(Tag::Image { .. }) => alt = Some(String::new()),
+                Event::Start(Tag::MetadataBlock(_)) => metadata = Some(String::new()),
+                Event::Text(text) | Event::Code(text) => {
+                    for buffer in [&mut heading, &mut alt, &mut metadata]
+                        .into_iter()
+                        .flatten()
+                    {
+                        buffer.push_str(&text);
+                    }
+                }
+                Event::SoftBreak | Event::HardBreak => {
+                    for buffer in [&mut heading, &mut alt].into_iter().flatten() {
+                        buffer.push(' ');
+                    }
+                }
+                Event::End(TagEnd::Heading(level)) => {
+                    let text = heading.take().unwrap_or_default();
+                    document.headings.push(Heading {
+                        level: heading_level(level),
+                        text: text.trim().to_string(),
+                    });
+                }
+                Event::End(TagEnd::Image) => {
+                    document
+                        .images
+                        .extend(alt.take().filter(|alt| !alt.is_empty()));
+                }
+                Event::End(TagEnd::MetadataBlock(kind)) => {
+                    let text = metadata.take().unwrap_or_default();
+                    document.front_matter = match kind {
+                        MetadataBlockKind::YamlStyle => parse_yaml(&text),
+                        MetadataBlockKind::PlusesStyle => parse_toml(&text),
+                    };
+                }
+                Event::Start(Tag::Link { dest_url, .. }) => {
+                    document.links.push(dest_url.to_string());
+                }
+                Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => {
+                    document
+                        .code_languages
+                        .extend(info.split_whitespace().next().map(String::from));
+                }
+                _ => {}
+            }
+        }
+
+        document.headings.retain(|heading| !heading.text.is_empty());
+        document.title = match document.get("title") {
+            Some(Value::List(_)) | None => document
+                .headings
+                .first()
+                .map(|heading| heading.text.clone()),
+            Some(title) => Some(title.to_string()),
+        };
+        document
+    }
+
+    /// The value of a key of the front matter, ignoring the case of the key.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.front_matter
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value)
+    }
+
+    /// The items of a list in the front matter. Text is read as a comma separated list, so tags
+    /// can be given as `tags: [a, b]`, `tags: a, b` or a list of `- a` lines.
+    pub fn list(&self, key: &str) -> Vec<String> {
+        match self.get(key) {
+            Some(Value::List(items)) => items.clone(),
+            Some(Value::Text(text)) => text
+                .split(',')
+                .map(unquote)
+                .filter(|item| !item.is_empty())
+                .collect(),
+            Some(value) => vec![value.to_string()],
+            None => Vec::new(),
+        }
+    }
+
+    /// The tags of the front matter, under `tags` or `tag`.
+    pub fn tags(&self) -> Vec<String> {
+        let tags = self.list("tags");
+        if tags.is_empty() {
+            self.list("tag")
+        } else {
+            tags
+        }
+    }
+}
+
+fn heading_level(level: HeadingLevel) -> u8 {
+    match level {
+        HeadingLevel::H1 => 1,
+        HeadingLevel::H2 => 2,
+        HeadingLevel::H3 => 3,
+        HeadingLevel::H4 => 4,
+        HeadingLevel::H5 => 5,
+        HeadingLevel::H6 => 6,
+    }
+}
+
+fn unquote(value: &str) -> String {
+    value.trim().trim_matches(['"', '\'']).to_string()
+}
+
+/// Read an inline list such as `[a, "b"]`, or else a single value.
+fn inline_value(value: &str) -> Value {
+    match value
+        .strip_prefix('[')
+        .and_then(|list| list.strip_suffix(']'))
+    {
+        Some(list) => Value::List(
+            list.split(',')
+                .map(unquote)
+                .filter(|item| !item.is_empty())
+                .collect(),
+        ),
+        None => Value::typed(&unquote(value)),
+    }
+}
+
+/// Read YAML front matter. Only the simple forms are understood: `key: value`, `key: [a, b]`, and
+/// a `key:` followed by a list of `- a` lines.
+fn parse_yaml(text: &str) -> Vec<(String, Value)> {
+    let mut entries: Vec<(String, Value)> = Vec::new();
+    // The key whose list is being read.
+    let mut list: Option<(String, Vec<String>)> = None;
+
+    for line in text.lines() {
+        if list.is_some() {
+            if let Some(item) = line.trim_start().strip_prefix("- ") {
+                if let Some((_, items)) = list.as_mut() {
+                    items.push(unquote(item));
+                }
+                continue;
+            }
+            if let Some((key, items)) = list.take() {
+                entries.push((key, Value::List(items)));
+            }
+        }
+
+        if line.starts_with([' ', '\t', '#']) {
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let (key, value) = (key.trim().to_string(), value.trim());
+        if value.is_empty() {
+            list = Some((key, Vec::new()));
+        } else {
+            entries.push((key, inline_value(value)));
+        }
+    }
+    if let Some((key, items)) = list {
+        entries.push((key, Value::List(items)));
+    }
+
+    entries
+}
+
+/// Read TOML front matter. Only `key = value` lines are understood, with strings, numbers, dates
+/// and lists of them as values; the keys of tables are given as `table.key`.
+fn parse_toml(text: &str) -> Vec<(String, Value)> {
+    let mut entries: Vec<(String, Value)> = Vec::new();
+    let mut table: Option<String> = None;
+
+    for line in text.lines().map(str::trim) {
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|name| name.strip_suffix(']'))
+        {
+            table = Some(name.trim().to_string());
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = unquote(key);
+        let key = match &table {
+            Some(table) => format!("{table}.{key}"),
+            None => key,
+        };
+        entries.push((key, inline_value(value.trim())));
+    }
+
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let contents =
+            "---\ntitle: \"Ownership\"\ntags:\n  - rust\n  - memory\nstatus: draft\n---\n\
+            # Borrowing\n\nSee [the book](https://doc.rust-lang.org/book/) and \
+            ![a diagram of `&mut`](img/mut.png).\n\n```rust ignore\n# not a heading\n```\n\
+            ## References ##\n#hashtag\n\n~~~\nplain\n~~~\n";
+
+        let result = Document::parse(contents);
+
+        assert_eq!(result.title, Some("Ownership".to_string()));
+        assert_eq!(
+            result.headings,
+            vec![
+                Heading {
+                    level: 1,
+                    text: "Borrowing".to_string()
+                },
+                Heading {
+                    level: 2,
+                    text: "References".to_string()
+                }
+            ]
+        );
+        assert_eq!(result.links, vec!["https://doc.rust-lang.org/book/"]);
+        assert_eq!(result.images, vec!["a diagram of &mut"]);
+        assert_eq!(result.code_languages, vec!["rust"]);
+        assert_eq!(result.tags(), vec!["rust", "memory"]);
+        assert_eq!(
+            result.get("status"),
+            Some(&Value::Text("draft".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_front_matter() {
+        let result = Document::parse("---\ntags: [rust, 'go']\n---\n# Title\n");
+        assert_eq!(result.tags(), vec!["rust", "go"]);
+        // Without a title in the front matter, the first heading is used.
+        assert_eq!(result.title, Some("Title".to_string()));
+
+        let result = Document::parse("---\ntag: a, b\n---\n");
+        assert_eq!(result.tags(), vec!["a", "b"]);
+
+        let result = Document::parse("No front matter.\n---\ntitle: x\n---\n");
+        assert_eq!(result.front_matter, Vec::new());
+        // Text underlined with `---` is a heading.
+        assert_eq!(result.title, Some("No front matter.".to_string()));
+
+        let result = Document::parse(
+            "+++\ntitle = \"Notes\"\ntags = [\"rust\", \"cli\"]\n\n[extra]\nweight = 3\n+++\nText.\n",
+        );
+        assert_eq!(result.title, Some("Notes".to_string()));
+        assert_eq!(result.tags(), vec!["rust", "cli"]);
+        assert_eq!(result.get("extra.weight"), Some(&Value::Number(3.0)));
+    }
+
+    #[test]
+    fn test_typed_values() {
+        let result = Document::parse(
+            "---\ndate: 2025-03-01\nupdated: \"2025-03-02T10:00:00\"\npriority: 2\n\
+            version: 1.2.3\nAuthor: Ada Lovelace\n---\n",
+        );
+
+        let date = |year, month, day| Value::Date(Date { year, month, day });
+        assert_eq!(result.get("date"), Some(&date(2025, 3, 1)));
+        assert_eq!(result.get("updated"), Some(&date(2025, 3, 2)));
+        assert_eq!(result.get("priority"), Some(&Value::Number(2.0)));
+        assert_eq!(
+            result.get("version"),
+            Some(&Value::Text("1.2.3".to_string()))
+        );
+        // Keys are found whatever their case.
+        assert_eq!(
+            result.get("author"),
+            Some(&Value::Text("Ada Lovelace".to_string()))
+        );
+
+        assert_eq!(Date::parse("2025-13-01"), None);
+        assert_eq!(Date::parse("2025-1-01"), None);
+        assert_eq!(date(2025, 3, 1).to_string(), "2025-03-01");
+    }
+}
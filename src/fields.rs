@@ -0,0 +1,166 @@
+use super::document::Document;
+use std::path::Path;
+
+/// A part of a document which can be searched on its own, e.g. with `title:ownership`.
+///
+/// Words of the body are stored in the vocabulary as they are, and words of the other fields
+/// with the name of the field in front (`title:ownership`), so each field has its own postings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Body,
+    /// The `title` of the front matter, otherwise the first heading.
+    Title,
+    Heading,
+    /// The `tags` of the front matter.
+    Tag,
+    /// The directories and name of the file.
+    Path,
+    /// The extension of the file.
+    Ext,
+    /// Fenced code blocks and inline code (see `text_process::split_markdown`).
+    Code,
+}
+
+impl Field {
+    pub const ALL: [Field; 7] = [
+        Field::Body,
+        Field::Title,
+        Field::Heading,
+        Field::Tag,
+        Field::Path,
+        Field::Ext,
+        Field::Code,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Field::Body => "body",
+            Field::Title => "title",
+            Field::Heading => "heading",
+            Field::Tag => "tag",
+            Field::Path => "path",
+            Field::Ext => "ext",
+            Field::Code => "code",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Field> {
+        Field::ALL.into_iter().find(|field| field.name() == name)
+    }
+
+    /// How much an occurrence of a word in this field counts for, relative to one in the body,
+    /// when ranking a word which is not limited to a field. The extension is only used to
+    /// filter.
+    pub fn boost(self) -> f64 {
+        match self {
+            Field::Body | Field::Code => 1.0,
+            Field::Title => 3.0,
+            Field::Heading | Field::Tag => 2.0,
+            Field::Path => 1.5,
+            Field::Ext => 0.0,
+        }
+    }
+
+    /// The field a vocabulary entry is in, from the name in front of its word.
+    pub fn of_term(term: &str) -> Field {
+        term.split_once(':')
+            .and_then(|(name, _)| Field::from_name(name))
+            .unwrap_or(Field::Body)
+    }
+
+    /// The vocabulary entry for a word in this field.
+    pub fn term(self, word: &str) -> String {
+        match self {
+            Field::Body => word.to_string(),
+            field => format!("{}:{word}", field.name()),
+        }
+    }
+}
+
+/// Find the text of the fields of a document other than its body (and its code, see
+/// `loading::extract_tokens`).
+pub fn document_fields(path: &str, document: &Document) -> Vec<(Field, String)> {
+    let headings: Vec<&str> = document
+        .headings
+        .iter()
+        .map(|heading| heading.text.as_str())
+        .collect();
+    let tags: Vec<String> = document.tags();
+
+    let mut fields: Vec<(Field, String)> = Vec::new();
+
+    if let Some(title) = &document.title {
+        fields.push((Field::Title, title.clone()));
+    }
+    if !headings.is_empty() {
+        fields.push((Field::Heading, headings.join("\n")));
+    }
+    if !tags.is_empty() {
+        fields.push((Field::Tag, tags.join("\n")));
+    }
+
+    let path = Path::new(path);
+    let mut components: Vec<String> = path
+        .parent()
+        .into_iter()
+        .flat_map(Path::components)
+        .map(|component| component.as_os_str().to_string_lossy().to_string())
+        .collect();
+    components.extend(
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().to_string()),
+    );
+    fields.push((Field::Path, components.join("\n")));
+
+    if let Some(ext) = path.extension() {
+        fields.push((Field::Ext, ext.to_string_lossy().to_lowercase()));
+    }
+
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_fields() {
+        let contents = "---\ntitle: \"Ownership\"\ntags:\n  - rust\n  - memory\n---\n\
+            # Borrowing\n\nSome text.\n\n```sh\n# not a heading\n```\n## References ##\n#hashtag\n";
+
+        let result = document_fields("notes/projects/borrow.md", &Document::parse(contents));
+
+        assert_eq!(
+            result,
+            vec![
+                (Field::Title, "Ownership".to_string()),
+                (Field::Heading, "Borrowing\nReferences".to_string()),
+                (Field::Tag, "rust\nmemory".to_string()),
+                (Field::Path, "notes\nprojects\nborrow".to_string()),
+                (Field::Ext, "md".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_front_matter() {
+        let result = document_fields("a.md", &Document::parse("---\ntags: [rust, 'go']\n---\n"));
+        assert_eq!(result[0], (Field::Tag, "rust\ngo".to_string()));
+
+        // Without a title in the front matter, the first heading is used.
+        let result = document_fields("a.txt", &Document::parse("# First\n## Second\n"));
+        assert_eq!(result[0], (Field::Title, "First".to_string()));
+    }
+
+    #[test]
+    fn test_field_terms() {
+        assert_eq!(Field::Body.term("sky"), "sky");
+        assert_eq!(Field::Title.term("sky"), "title:sky");
+        assert_eq!(Field::from_name("tag"), Some(Field::Tag));
+        assert_eq!(Field::Code.term("fn"), "code:fn");
+        assert_eq!(Field::from_name("colour"), None);
+        assert_eq!(Field::of_term("title:sky"), Field::Title);
+        assert_eq!(Field::of_term("sky"), Field::Body);
+        assert_eq!(Field::of_term("colour:sky"), Field::Body);
+    }
+}
@@ -0,0 +1,191 @@
+use super::vocabulary::TermId;
+use std::collections::HashMap;
+
+/// The Damerau-Levenshtein distance between two words: the fewest insertions, deletions,
+/// substitutions and transpositions of adjacent characters which turn one into the other.
+///
+/// This is the unrestricted distance (e.g. "ca" to "abc" is 2), which unlike the optimal string
+/// alignment distance is a metric, as a `BkTree` needs.
+pub fn damerau_levenshtein(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let (n, m) = (a.len(), b.len());
+    let max = n + m;
+
+    // `d[i + 1][j + 1]` is the distance between `a[..i]` and `b[..j]`; the extra first row and
+    // column hold `max` so that transpositions never reach past the start of either word.
+    let mut d: Vec<Vec<usize>> = vec![vec![0; m + 2]; n + 2];
+    d[0][0] = max;
+    for i in 0..=n {
+        d[i + 1][0] = max;
+        d[i + 1][1] = i;
+    }
+    for j in 0..=m {
+        d[0][j + 1] = max;
+        d[1][j + 1] = j;
+    }
+
+    // The last row of `a` in which each character was seen.
+    let mut last_row: HashMap<char, usize> = HashMap::new();
+
+    for i in 1..=n {
+        // The last column of `b` in this row in which the characters matched.
+        let mut last_match = 0;
+
+        for j in 1..=m {
+            let i1 = last_row.get(&b[j - 1]).copied().unwrap_or(0);
+            let j1 = last_match;
+            let cost = if a[i - 1] == b[j - 1] {
+                last_match = j;
+                0
+            } else {
+                1
+            };
+
+            d[i + 1][j + 1] = (d[i][j] + cost)
+                .min(d[i + 1][j] + 1)
+                .min(d[i][j + 1] + 1)
+                .min(d[i1][j1] + (i - i1 - 1) + 1 + (j - j1 - 1));
+        }
+
+        last_row.insert(a[i - 1], i);
+    }
+
+    d[n + 1][m + 1]
+}
+
+/// A BK-tree of terms, for finding the terms within an edit distance of a word without
+/// comparing it against every term.
+///
+/// Each child of a node is filed under its distance from the node, so by the triangle inequality
+/// only the children whose distance is within the search distance of the word's distance from
+/// the node can hold matches. The tree holds term ids; the terms themselves are passed in.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BkTree {
+    nodes: Vec<BkNode>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct BkNode {
+    id: TermId,
+    /// Distance from this node and index of each child.
+    children: Vec<(usize, usize)>,
+}
+
+impl BkTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a term to the tree. `terms` holds the text of every term by id.
+    pub fn insert(&mut self, terms: &[String], id: TermId) {
+        let new_node = BkNode {
+            id,
+            children: Vec::new(),
+        };
+        if self.nodes.is_empty() {
+            self.nodes.push(new_node);
+            return;
+        }
+
+        let mut node = 0;
+        loop {
+            let distance = damerau_levenshtein(&terms[self.nodes[node].id], &terms[id]);
+            if distance == 0 {
+                return;
+            }
+
+            let child = self.nodes[node]
+                .children
+                .iter()
+                .find(|&&(d, _)| d == distance)
+                .map(|&(_, child)| child);
+
+            match child {
+                Some(child) => node = child,
+                None => {
+                    let index = self.nodes.len();
+                    self.nodes.push(new_node);
+                    self.nodes[node].children.push((distance, index));
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Find the terms within `max_distance` of a word, with their distances, in no particular
+    /// order.
+    pub fn find(&self, terms: &[String], word: &str, max_distance: usize) -> Vec<(TermId, usize)> {
+        let mut found: Vec<(TermId, usize)> = Vec::new();
+        let mut pending: Vec<usize> = if self.nodes.is_empty() {
+            Vec::new()
+        } else {
+            vec![0]
+        };
+
+        while let Some(node) = pending.pop() {
+            let node = &self.nodes[node];
+            let distance = damerau_levenshtein(word, &terms[node.id]);
+            if distance <= max_distance {
+                found.push((node.id, distance));
+            }
+
+            pending.extend(
+                node.children
+                    .iter()
+                    .filter(|&&(d, _)| d.abs_diff(distance) <= max_distance)
+                    .map(|&(_, child)| child),
+            );
+        }
+
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_damerau_levenshtein() {
+        assert_eq!(damerau_levenshtein("borrow", "borrow"), 0);
+        assert_eq!(damerau_levenshtein("borow", "borrow"), 1);
+        assert_eq!(damerau_levenshtein("teh", "the"), 1);
+        assert_eq!(damerau_levenshtein("kitten", "sitting"), 3);
+        assert_eq!(damerau_levenshtein("ca", "abc"), 2);
+        assert_eq!(damerau_levenshtein("", "sky"), 3);
+    }
+
+    #[test]
+    fn test_bk_tree_find() {
+        let terms: Vec<String> = ["borrow", "borrowed", "burrow", "bow", "checker", "borrow"]
+            .iter()
+            .map(|term| term.to_string())
+            .collect();
+        let mut tree = BkTree::new();
+        for id in 0..terms.len() {
+            tree.insert(&terms, id);
+        }
+
+        let mut result = tree.find(&terms, "borow", 1);
+        result.sort_unstable();
+        assert_eq!(result, vec![(0, 1)]);
+
+        let mut result = tree.find(&terms, "borow", 2);
+        result.sort_unstable();
+        assert_eq!(result, vec![(0, 1), (2, 2), (3, 2)]);
+
+        // The same as comparing against every term.
+        for word in ["checkr", "bo", "borrowing"] {
+            let mut result = tree.find(&terms, word, 3);
+            result.sort_unstable();
+            let expected: Vec<(TermId, usize)> = terms[..5]
+                .iter()
+                .enumerate()
+                .map(|(id, term)| (id, damerau_levenshtein(word, term)))
+                .filter(|&(_, d)| d <= 3)
+                .collect();
+            assert_eq!(result, expected);
+        }
+    }
+}
@@ -0,0 +1,21 @@
+/*!
+# Sesquioxide
+
+A library to load all of the text and markdown files below a directory, build a tf-idf model over
+them and rank the documents against a query.
+*/
+pub mod calculations;
+pub mod document;
+pub mod fields;
+pub mod fuzzy;
+pub mod loading;
+pub mod metadata;
+pub mod model;
+pub mod passages;
+pub mod query;
+pub mod scoring;
+pub mod snippets;
+pub mod storage;
+pub mod text_process;
+pub mod vocabulary;
+pub mod watch;
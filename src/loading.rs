@@ -1,27 +1,30 @@
-use super::model::Model;
-use super::text_process;
-use std::collections::HashMap;
-use std::fs;
+use super::document::Document;
+use super::fields::{self, Field};
+use super::model::{DocumentInfo, Model};
+use super::passages::{split_passages, Splitting};
+use super::text_process::{split_markdown, Analyzer, Token};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, Metadata};
 use std::path::Path;
+use std::time::UNIX_EPOCH;
 use walkdir::WalkDir;
 
-fn walk_directory(dir_name: &str) -> Vec<String> {
+/// Whether a path names a markdown or .txt file, judging by its extension.
+pub fn is_text_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("txt"))
+}
+
+/// List the markdown and .txt files below a directory, in file name order.
+pub fn walk_directory(dir_name: &str) -> Vec<String> {
     let mut path_list: Vec<String> = Vec::new();
     for entry in WalkDir::new(dir_name)
+        .sort_by_file_name()
         .into_iter()
         .filter_map(Result::ok)
         .filter(|e| !e.file_type().is_dir())
     {
-        let f_name: String = String::from(entry.file_name().to_string_lossy());
-
-        let path_name = Path::new(&f_name);
-        if path_name
-            .extension()
-            .map_or(false, |ext| ext.eq_ignore_ascii_case("md"))
-            || path_name
-                .extension()
-                .map_or(false, |ext| ext.eq_ignore_ascii_case("txt"))
-        {
+        if is_text_file(entry.path()) {
             if let Some(res) = entry.path().to_str() {
                 path_list.push(res.to_string());
             }
@@ -45,79 +48,325 @@ pub fn load_paths(dir_name: &str) -> Result<Vec<String>, &str> {
     }
 }
 
-/// Load a corpus from a list of paths.
-pub fn load_corpus(path_list: &[String]) -> Result<Vec<Vec<String>>, &str> {
-    let mut corpus: Vec<Vec<String>> = Vec::new();
+/// Get the modification time of a file in nanoseconds since the Unix epoch (0 if unavailable).
+/// Whole seconds would miss an edit which keeps the size of a file within a second of the last.
+fn modified_time(meta: &Metadata) -> u64 {
+    meta.modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |dur| u64::try_from(dur.as_nanos()).unwrap_or(u64::MAX))
+}
+
+/// Hash the contents of a file (64 bit FNV-1a, which is stable between builds and platforms).
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Read a file, returning its contents and its document table entry (without a token count).
+fn read_file(path: &str) -> Result<(DocumentInfo, String), String> {
+    let meta = fs::metadata(path).map_err(|err| err.to_string())?;
+    let bytes = fs::read(path).map_err(|err| err.to_string())?;
+    let (size, hash) = (bytes.len() as u64, content_hash(&bytes));
+    let contents = String::from_utf8(bytes).unwrap_or_default();
+
+    let info = DocumentInfo {
+        path: path.to_string(),
+        length: 0,
+        mtime: modified_time(&meta),
+        size,
+        hash,
+        metadata: Document::parse(&contents),
+        passage: None,
+    };
+
+    Ok((info, contents))
+}
+
+/// Load a file into tokenised vectors along with their document table entries: one for the
+/// whole file, or one for each passage if it is split (see `split_units`).
+pub fn load_document(
+    path: &str,
+    analyzer: &Analyzer,
+    splitting: Splitting,
+) -> Result<Vec<(DocumentInfo, Vec<Token>)>, String> {
+    let (info, contents) = read_file(path)?;
+    split_units(info, &contents, analyzer, splitting)
+}
+
+/// Load a corpus from a list of paths, along with the document table describing it.
+///
+/// Files from which no words can be extracted are skipped, and split files have several
+/// entries, so the document table (rather than `path_list`) gives the path of each document in
+/// the corpus.
+pub fn load_corpus(
+    path_list: &[String],
+    analyzer: &Analyzer,
+    splitting: Splitting,
+) -> Result<(Vec<DocumentInfo>, Vec<Vec<Token>>), &'static str> {
+    let mut documents: Vec<DocumentInfo> = Vec::new();
+    let mut corpus: Vec<Vec<Token>> = Vec::new();
 
     for path_string in path_list {
-        if let Ok(res) = extract_contents(path_string) {
-            corpus.push(res);
+        if let Ok(units) = load_document(path_string, analyzer, splitting) {
+            for (info, res) in units {
+                documents.push(info);
+                corpus.push(res);
+            }
         };
     }
 
     if corpus.is_empty() {
         Err("No words extracted from files.")
     } else {
-        Ok(corpus)
+        Ok((documents, corpus))
     }
 }
 
-/// Load the contents of a file into a tokenised vector.
+/// Load the prose of a file (see `split_markdown`) into a tokenised vector.
 pub fn extract_contents(path: &str) -> Result<Vec<String>, String> {
-    let contents: String = fs::read_to_string(path).map_or_else(|_| String::new(), |res| res);
+    let contents: String = fs::read_to_string(path).unwrap_or_default();
+
+    extract_words(&split_markdown(&contents).prose, &Analyzer::default())
+        .map(|tokens| tokens.into_iter().map(|token| token.text).collect())
+}
+
+/// Tokenise the contents of a file, keeping the positions of the words.
+fn extract_words(contents: &str, analyzer: &Analyzer) -> Result<Vec<Token>, String> {
+    let words: Vec<Token> = analyzer.analyse(contents);
+
+    if words.is_empty() {
+        Err(String::from("No words found."))
+    } else {
+        Ok(words)
+    }
+}
+
+/// Tokenise a file as a whole, or each passage of it (see `split_passages`), with the document
+/// table entry of each. A passage has the path, title and front matter of its file, and the
+/// headings and links in its own text. Passages without words of their own are left out.
+fn split_units(
+    info: DocumentInfo,
+    contents: &str,
+    analyzer: &Analyzer,
+    splitting: Splitting,
+) -> Result<Vec<(DocumentInfo, Vec<Token>)>, String> {
+    if splitting == Splitting::Document {
+        let (tokens, length) = extract_tokens(&info, contents, analyzer)?;
+        return Ok(vec![(DocumentInfo { length, ..info }, tokens)]);
+    }
+
+    let units: Vec<(DocumentInfo, Vec<Token>)> = split_passages(contents, splitting)
+        .into_iter()
+        .filter_map(|(passage, text)| {
+            let own = Document::parse(text);
+            let passage_info = DocumentInfo {
+                metadata: Document {
+                    headings: own.headings,
+                    links: own.links,
+                    images: own.images,
+                    code_languages: own.code_languages,
+                    ..info.metadata.clone()
+                },
+                passage: Some(passage),
+                ..info.clone()
+            };
+            let (tokens, length) = extract_tokens(&passage_info, text, analyzer).ok()?;
+            Some((
+                DocumentInfo {
+                    length,
+                    ..passage_info
+                },
+                tokens,
+            ))
+        })
+        .collect();
+
+    if units.is_empty() {
+        Err(String::from("No words found."))
+    } else {
+        Ok(units)
+    }
+}
+
+/// Tokenise a document: the words of its body followed by the words of its other fields (see
+/// `fields::document_fields`) and of its code, along with the number of words in all of them.
+/// The fields are taken from the path and metadata of its document table entry.
+///
+/// The body is the prose of the document (see `split_markdown`). The words of each field are
+/// numbered from zero, so phrases can be matched within a field. A document without words in its
+/// body, title, headings, tags or code is not tokenised, nor is a passage without words in its
+/// body, headings or code.
+fn extract_tokens(
+    info: &DocumentInfo,
+    contents: &str,
+    analyzer: &Analyzer,
+) -> Result<(Vec<Token>, u32), String> {
+    let markdown = split_markdown(contents);
+    let mut tokens: Vec<Token> = analyzer.analyse(&markdown.prose);
+    let mut found = !tokens.is_empty();
+
+    let mut fields: Vec<(Field, String)> = fields::document_fields(&info.path, &info.metadata);
+    if !markdown.code.is_empty() {
+        fields.push((Field::Code, markdown.code.join("\n")));
+    }
+    for (field, text) in fields {
+        let Ok(words) = extract_words(&text, analyzer) else {
+            continue;
+        };
+        found |= match field {
+            Field::Path | Field::Ext => false,
+            // A passage shares these with the rest of its file.
+            Field::Title | Field::Tag => info.passage.is_none(),
+            _ => true,
+        };
+        tokens.extend(words.into_iter().map(|word| Token {
+            text: field.term(&word.text),
+            ..word
+        }));
+    }
 
-    let cleaned_text = text_process::clean_up_text(&contents);
+    if !found {
+        return Err(String::from("No words found."));
+    }
+    let length = tokens.len() as u32;
+    Ok((tokens, length))
+}
 
-    let words: Result<Vec<String>, String> = text_process::tokenise(&cleaned_text);
+/// Counts of the documents affected by an update.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct UpdateSummary {
+    pub added: usize,
+    pub modified: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+}
 
-    match words {
-        Ok(words) if words.is_empty() => Err(String::from("Zero len vector.")),
-        Ok(words) => Ok(words),
-        Err(message) => Err(message),
+impl UpdateSummary {
+    pub fn is_unchanged(&self) -> bool {
+        self.added == 0 && self.modified == 0 && self.removed == 0
     }
 }
 
-/// Process user input and a tf-idf vector from it.
-pub fn process_input(
-    test_input: &[String],
-    all_words: &HashMap<String, i32>,
-    model: &Model,
-) -> Result<Vec<f64>, String> {
-    if test_input.is_empty() {
-        return Err(String::from("Empty query"));
+/// Bring the documents for a set of paths up to date with the files on disk.
+///
+/// Paths which no longer exist (or no longer contain any words) are removed from the model, and
+/// new or changed files are (re-)read. A file is only read if its modification time or size
+/// differs from the document table, and only re-tokenised if its content hash differs too.
+/// Files are analysed with the analyzer of the model and split into passages the way it was.
+/// The counts are of files, however many passages each has.
+pub fn update_documents(model: &mut Model, paths: &[String]) -> UpdateSummary {
+    let mut summary = UpdateSummary::default();
+    let mut stale: Vec<usize> = Vec::new();
+    let mut fresh: Vec<(DocumentInfo, Vec<Token>)> = Vec::new();
+
+    // The documents read from each path: one for the whole file, or one for each passage.
+    let mut known: HashMap<String, Vec<usize>> = HashMap::new();
+    for (i, doc) in model.documents.iter().enumerate() {
+        known.entry(doc.path.clone()).or_default().push(i);
+    }
+
+    for path in paths {
+        let existing: &[usize] = known.get(path).map_or(&[], Vec::as_slice);
+
+        let meta = match fs::metadata(path) {
+            Ok(meta) if meta.is_file() => meta,
+            _ => {
+                if !existing.is_empty() {
+                    stale.extend(existing);
+                    summary.removed += 1;
+                }
+                continue;
+            }
+        };
+
+        if let Some(&doc) = existing.first() {
+            let info = &model.documents[doc];
+            if info.mtime == modified_time(&meta) && info.size == meta.len() {
+                summary.unchanged += 1;
+                continue;
+            }
+        }
+
+        let loaded = read_file(path).and_then(|(info, contents)| {
+            if let Some(&doc) = existing.first() {
+                if model.documents[doc].hash == info.hash {
+                    return Ok((info, None));
+                }
+            }
+            split_units(info.clone(), &contents, &model.analyzer, model.splitting)
+                .map(|units| (info, Some(units)))
+        });
+
+        match loaded {
+            // Touched, but the contents are the same: only the document table needs updating.
+            Ok((info, None)) => {
+                for &doc in existing {
+                    let entry = &mut model.documents[doc];
+                    entry.mtime = info.mtime;
+                    entry.size = info.size;
+                }
+                summary.unchanged += 1;
+            }
+            Ok((_, Some(units))) => {
+                fresh.extend(units);
+                if existing.is_empty() {
+                    summary.added += 1;
+                } else {
+                    stale.extend(existing);
+                    summary.modified += 1;
+                }
+            }
+            Err(_) if !existing.is_empty() => {
+                stale.extend(existing);
+                summary.removed += 1;
+            }
+            Err(_) => {}
+        }
+    }
+
+    if !summary.is_unchanged() {
+        model.remove_documents(&stale);
+        for (info, words) in fresh {
+            model.add_document(info, &words);
+        }
+        model.update_statistics();
     }
 
-    let mut test_vec: Vec<f64> = vec![0.0; model.idf.len()];
+    summary
+}
+
+/// Bring a model up to date with the markdown and .txt files below a directory: new files are
+/// added, and files which have changed or been deleted since the model was built are updated.
+pub fn sync_directory(model: &mut Model, dir_name: &str) -> UpdateSummary {
+    let mut paths: Vec<String> = walk_directory(dir_name);
 
-    // Create a vector from the input (only consider words from the input which are already in the
-    // corpus, and use idf values as calculated for the corpus).
-    for (c, (word, _count)) in all_words.iter().enumerate() {
-        if test_input.contains(word) {
-            test_vec[c] += 1.0 * model.idf[c];
+    let found: HashSet<String> = paths.iter().cloned().collect();
+    for doc in &model.documents {
+        if !found.contains(&doc.path) {
+            paths.push(doc.path.clone());
         }
     }
 
-    Ok(test_vec)
+    update_documents(model, &paths)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::text_process::{Language, Stem};
 
     #[test]
     fn test_load_paths_ok() {
-        let result: Result<Vec<String>, &str> = load_paths("new_test");
+        let result: Result<Vec<String>, &str> = load_paths("data");
 
-        // This assertion may fail if the system serves files in a different order to the vector
-        // given below.
         assert_eq!(
             Ok(vec![
-                "new_test/RustNotes.md".to_string(),
-                "new_test/poem.txt".to_string(),
-                "new_test/doc4.txt".to_string(),
-                "new_test/doc1.txt".to_string(),
-                "new_test/doc3.txt".to_string(),
-                "new_test/doc2.txt".to_string()
+                "data/doc1.txt".to_string(),
+                "data/doc2.txt".to_string(),
+                "data/doc3.txt".to_string(),
+                "data/doc4.txt".to_string()
             ]),
             result
         );
@@ -132,26 +381,140 @@ mod tests {
 
     #[test]
     fn test_load_corpus_ok() {
-        let paths: Vec<String> = vec!["new_test/doc1.txt".to_string()];
-        let result: Result<Vec<Vec<String>>, &str> = load_corpus(&paths);
+        let paths: Vec<String> = vec!["data/doc1.txt".to_string(), "abcdefg.txt".to_string()];
+        let (documents, corpus) =
+            load_corpus(&paths, &Analyzer::default(), Splitting::Document).unwrap();
 
+        assert_eq!(corpus.len(), 1);
         assert_eq!(
-            Ok(vec![vec!["sky".to_string(), "blue".to_string()]]),
-            result
+            vec![
+                Token {
+                    text: "sky".to_string(),
+                    position: 1,
+                    span: 4..7
+                },
+                Token {
+                    text: "blue".to_string(),
+                    position: 3,
+                    span: 11..15
+                }
+            ],
+            corpus[0][..2]
+        );
+        // The other fields follow the body.
+        let fields: Vec<&str> = corpus[0][2..]
+            .iter()
+            .map(|token| token.text.as_str())
+            .collect();
+        // Words of letters and digits are followed by their parts.
+        assert_eq!(
+            vec!["path:data", "path:doc1", "path:doc", "path:1", "ext:txt"],
+            fields
+        );
+        assert_eq!(documents.len(), 1);
+        assert_eq!(documents[0].path, "data/doc1.txt");
+        assert_eq!(documents[0].length, 7);
+        assert_eq!(documents[0].size, 17);
+        assert_eq!(documents[0].hash, content_hash(b"The sky is blue.\n"));
+        assert!(documents[0].mtime > 0);
+    }
+
+    #[test]
+    fn test_load_corpus_stemmed() {
+        let paths: Vec<String> = vec!["data/doc4.txt".to_string()];
+        let analyzer = Analyzer::default().with_token_filter(Box::new(Stem {
+            language: Language::English,
+        }));
+        let (_, corpus) = load_corpus(&paths, &analyzer, Splitting::Document).unwrap();
+
+        let words: Vec<&str> = corpus[0][..5]
+            .iter()
+            .map(|token| token.text.as_str())
+            .collect();
+        assert_eq!(vec!["see", "shine", "sun", "bright", "sun"], words);
+    }
+
+    fn entry(path: &str, contents: &str) -> DocumentInfo {
+        DocumentInfo {
+            path: path.to_string(),
+            metadata: Document::parse(contents),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_extract_tokens_markdown() {
+        let contents = "---\ntitle: Loading\n---\nCall `loadCorpus` first, see \
+            https://example.com/docs.\n\n```rust\nlet corpus = load();\n```\n";
+
+        let (tokens, length) =
+            extract_tokens(&entry("notes.md", contents), contents, &Analyzer::default()).unwrap();
+
+        let words: Vec<&str> = tokens.iter().map(|token| token.text.as_str()).collect();
+        assert_eq!(length, 10);
+        assert_eq!(
+            vec![
+                "call",
+                "first",
+                "see",
+                "title:loading",
+                "path:notes",
+                "ext:md",
+                "code:loadcorpus",
+                "code:let",
+                "code:corpus",
+                "code:load"
+            ],
+            words
+        );
+
+        // Code alone is enough for a document to be indexed, but a path is not.
+        let code = "```\nfn main() {}\n```\n";
+        assert!(extract_tokens(&entry("a.md", code), code, &Analyzer::default()).is_ok());
+        let nothing = "$$x$$ https://example.com";
+        assert!(extract_tokens(&entry("a.md", nothing), nothing, &Analyzer::default()).is_err());
+    }
+
+    #[test]
+    fn test_extract_tokens_colons() {
+        let contents = "title:foo bar\n";
+        let analyzer = Analyzer::from_config("tokenizer whitespace").unwrap();
+
+        let (tokens, _) =
+            extract_tokens(&entry("notes.txt", contents), contents, &analyzer).unwrap();
+
+        // A word of the body which looks like one of a field is split instead.
+        let words: Vec<&str> = tokens.iter().map(|token| token.text.as_str()).collect();
+        assert_eq!(vec!["title", "foo", "bar", "path:notes", "ext:txt"], words);
+    }
+
+    #[test]
+    fn test_extract_tokens_toml_front_matter() {
+        let contents = "+++\ntitle = \"Loading\"\ndraft = true\n+++\nCall it first.\n";
+
+        let (tokens, length) =
+            extract_tokens(&entry("notes.md", contents), contents, &Analyzer::default()).unwrap();
+
+        // The keys and values of the front matter are not words of the body.
+        let words: Vec<&str> = tokens.iter().map(|token| token.text.as_str()).collect();
+        assert_eq!(length, 5);
+        assert_eq!(
+            vec!["call", "first", "title:loading", "path:notes", "ext:md"],
+            words
         );
     }
 
     #[test]
     fn test_load_corpus_error() {
         let paths: Vec<String> = vec!["abcdefg.txt".to_string()];
-        let result: Result<Vec<Vec<String>>, &str> = load_corpus(&paths);
+        let result = load_corpus(&paths, &Analyzer::default(), Splitting::Document);
 
         assert_eq!(Err("No words extracted from files."), result);
     }
 
     #[test]
     fn test_extract_contents_ok() {
-        let result: Result<Vec<String>, String> = extract_contents("new_test/doc1.txt");
+        let result: Result<Vec<String>, String> = extract_contents("data/doc1.txt");
 
         assert_eq!(Ok(vec!["sky".to_string(), "blue".to_string()]), result);
 
@@ -168,47 +531,125 @@ mod tests {
     }
 
     #[test]
-    fn test_process_input_ok() {
-        let test_input: Vec<String> = vec![
-            "this is some text".to_string(),
-            "and there is more".to_string(),
-            "this too".to_string(),
-        ];
-
-        let mut words: HashMap<String, i32> = HashMap::new();
-        words.insert("this".to_string(), 2);
-        words.insert("is".to_string(), 2);
-        words.insert("some".to_string(), 1);
-        words.insert("text".to_string(), 1);
-        words.insert("and".to_string(), 1);
-        words.insert("there".to_string(), 1);
-        words.insert("more".to_string(), 1);
-        words.insert("too".to_string(), 1);
+    fn test_content_hash() {
+        assert_eq!(content_hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(content_hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_ne!(content_hash(b"sky"), content_hash(b"yks"));
+    }
 
-        let idf_vals: Vec<f64> = vec![1.0];
+    #[test]
+    fn test_update_documents() {
+        let dir = std::env::temp_dir().join(format!("sesquioxide-update-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+
+        fs::write(path("a.txt"), "sky blue").unwrap();
+        fs::write(path("b.txt"), "sun bright").unwrap();
+
+        let (documents, corpus) = load_corpus(
+            &walk_directory(&path("")),
+            &Analyzer::default(),
+            Splitting::Document,
+        )
+        .unwrap();
+        let mut model = Model::new(documents, &corpus);
+
+        // Nothing has changed.
+        let summary = sync_directory(&mut model, &path(""));
+        assert_eq!(summary.unchanged, 2);
+        assert!(summary.is_unchanged());
+
+        fs::write(path("b.txt"), "sun moon").unwrap();
+        fs::remove_file(path("a.txt")).unwrap();
+        fs::write(path("c.md"), "sky grey").unwrap();
+
+        let summary = sync_directory(&mut model, &path(""));
+        assert_eq!(
+            summary,
+            UpdateSummary {
+                added: 1,
+                modified: 1,
+                removed: 1,
+                unchanged: 0,
+            }
+        );
 
-        let result: Result<Vec<f64>, String> = process_input(&test_input, &words, &idf_vals);
+        let moon = model.vocabulary.id("moon").unwrap();
+        let bright = model.vocabulary.id("bright").unwrap();
+        assert_eq!(model.documents.len(), 2);
+        assert_eq!(model.find_document(&path("a.txt")), None);
+        assert_eq!(model.vocabulary.doc_freq(moon), 1);
+        assert_eq!(model.vocabulary.doc_freq(bright), 0);
+
+        // An edit keeping the size, within the same second as the last, is still seen.
+        let set_modified = |nanos: u64| {
+            let file = fs::File::options().write(true).open(path("b.txt")).unwrap();
+            let time = UNIX_EPOCH + std::time::Duration::from_nanos(nanos);
+            file.set_modified(time).unwrap();
+        };
+        let second: u64 = 1_700_000_000_000_000_000;
+        set_modified(second + 1_000);
+        sync_directory(&mut model, &path(""));
+        fs::write(path("b.txt"), "sun star").unwrap();
+        set_modified(second + 2_000);
+
+        let summary = sync_directory(&mut model, &path(""));
+        assert_eq!(summary.modified, 1);
+        assert_eq!(
+            model
+                .vocabulary
+                .doc_freq(model.vocabulary.id("star").unwrap()),
+            1
+        );
 
-        assert_eq!(Ok(vec![0.0000000000000000]), result);
+        fs::remove_dir_all(&dir).unwrap();
     }
 
     #[test]
-    fn test_process_input_error() {
-        let mut words: HashMap<String, i32> = HashMap::new();
-        words.insert("this".to_string(), 2);
-        words.insert("is".to_string(), 2);
-        words.insert("some".to_string(), 1);
-        words.insert("text".to_string(), 1);
-        words.insert("and".to_string(), 1);
-        words.insert("there".to_string(), 1);
-        words.insert("more".to_string(), 1);
-        words.insert("too".to_string(), 1);
-
-        let idf_vals: Vec<f64> = vec![1.0];
-
-        // test empty query
-        let error_res: Result<Vec<f64>, String> = process_input(&[], &words, &idf_vals);
-
-        assert_eq!(Err(String::from("Empty query")), error_res);
+    fn test_update_documents_passages() {
+        let dir = std::env::temp_dir().join(format!("sesquioxide-passages-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+
+        fs::write(
+            path("notes.md"),
+            "---\ntitle: Notes\n---\n# Sky\n\nThe sky is blue.\n\n# Sun\n\nThe sun is bright.\n",
+        )
+        .unwrap();
+
+        let (documents, corpus) = load_corpus(
+            &walk_directory(&path("")),
+            &Analyzer::default(),
+            Splitting::Heading,
+        )
+        .unwrap();
+        let mut model = Model::new(documents, &corpus);
+        model.splitting = Splitting::Heading;
+
+        let locations: Vec<String> = model.documents.iter().map(DocumentInfo::location).collect();
+        assert_eq!(
+            locations,
+            vec![
+                format!("{}:4 — Sky", path("notes.md")),
+                format!("{}:8 — Sun", path("notes.md")),
+            ]
+        );
+        // Passages have the title of their file, and their own headings.
+        assert_eq!(model.documents[1].metadata.title.as_deref(), Some("Notes"));
+        assert_eq!(model.documents[1].metadata.headings.len(), 1);
+
+        fs::write(path("notes.md"), "# Moon\n\nThe moon is grey.\n").unwrap();
+
+        let summary = sync_directory(&mut model, &path(""));
+        assert_eq!(summary.modified, 1);
+        assert_eq!(model.documents.len(), 1);
+        assert_eq!(
+            model
+                .vocabulary
+                .doc_freq(model.vocabulary.id("sky").unwrap()),
+            0
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
@@ -1,10 +1,15 @@
-mod calculations;
-mod loading;
-mod model;
-mod text_process;
-
-use model::Model;
+use sesquioxide::calculations::{OutputFormat, ResultOptions};
+use sesquioxide::model::Model;
+use sesquioxide::passages::Splitting;
+use sesquioxide::query::QueryOptions;
+use sesquioxide::scoring::{self, Scorer, ScorerOptions};
+use sesquioxide::text_process::{Analyzer, AsciiFolding, Stem};
+use sesquioxide::{calculations, loading, query, storage, text_process, watch};
 use std::env;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, PoisonError, RwLock};
 
 /**
 # Sesquioxide
@@ -12,35 +17,363 @@ use std::env;
 A program to recursively load all of the text and markdown files in a directory and perform a
 tf-idf calculation on them. This calculation is then used to compare command line input to all
 of the documents and provide the file names of those which are most similar to the user input.
+
+Usage:
+
+- `sesquioxide [dir]`: build a model of `dir` in memory and search it.
+- `sesquioxide index [dir] [index file]`: build a model of `dir` and save it to an index file
+  (by default `dir/.sesquioxide.idx`). If the index already exists, only files which have
+  changed since it was written are re-read.
+- `sesquioxide search [index file or dir]`: load a saved index and search it.
+
+Options for searching:
+
+- `--watch`: keep the model up to date with changes to the files in the directory.
+- `--ranker <tfidf|bm25|bm25+|dirichlet|jm>`: the ranking function (default `tfidf`), with
+  `--k1 <k1>`, `--b <b>` and `--delta <delta>` to tune BM25 and BM25+, and `--mu <mu>` and
+  `--lambda <lambda>` to tune the Dirichlet and Jelinek-Mercer smoothed query likelihood models.
+- `--proximity <weight>`: how strongly to favour documents where the query terms occur close
+  together (default 0.5, 0 to turn off, less than 1).
+- `--expansions <n>`: the most words a wildcard such as `borrow*`, or a misspelt word such as
+  `borow~1`, is expanded to (default 50).
+- `--exact`: do not look for similar words when a query word is not found.
+- `--by-document`: when files are split into passages, show each file once, at its best passage.
+- `--snippets <n>`: the most snippets of matched text to show with each result (default 2, 0 to
+  turn off). The matched words are highlighted when writing to a terminal.
+- `--json`: write the results of each query as a line of JSON, with the matched words of
+  snippets between `<mark>` and `</mark>`.
+- `--explain`: show how the score of each result is made up: what each query term adds, from
+  its frequency in each field and the IDF and length normalisation of the ranker, and how the
+  sum is normalised and boosted for proximity. A query starting with `:explain` is explained on
+  its own.
+
+Options for building a model:
+
+- `--stem <language|none>`: reduce words to their stems with the Snowball stemmer for a language
+  (e.g. `english`), so that "borrowing" also finds "borrowed" (default `none`).
+- `--fold`: remove accents from letters, so that "cafe" also finds "café".
+- `--analyzer <config file>`: analyse the text of the files with the character filters,
+  tokenizer and token filters listed in a config file (see `Analyzer::from_config`) instead.
+- `--passages <heading|paragraph|window>`: index and rank passages of the files rather than
+  whole files, shown as `file:line — section`. Windows hold `--window <words>` words (default
+  200) and overlap by `--overlap <words>` (default 50).
+
+The analysis and passages are saved in the index and used for its queries and updates;
+re-indexing with a different `--stem`, `--fold`, `--analyzer` or `--passages` rebuilds the
+index.
 */
 fn main() {
-    // Use the directory supplied in arguments, otherwise use the directory in which the program is
-    // run.
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+
+    let watch_files = take_flag(&mut args, "--watch");
+
+    let defaults = ScorerOptions::default();
+    let scorer_options = ScorerOptions {
+        k1: exit_on_error(take_number(&mut args, "--k1", defaults.k1)),
+        b: exit_on_error(take_number(&mut args, "--b", defaults.b)),
+        delta: exit_on_error(take_number(&mut args, "--delta", defaults.delta)),
+        mu: exit_on_error(take_number(&mut args, "--mu", defaults.mu)),
+        lambda: exit_on_error(take_number(&mut args, "--lambda", defaults.lambda)),
+        proximity: exit_on_error(take_number(&mut args, "--proximity", defaults.proximity)),
+    };
+    let ranker = exit_on_error(take_option(&mut args, "--ranker")).unwrap_or(String::from("tfidf"));
+    let scorer: Box<dyn Scorer> =
+        exit_on_error(scoring::scorer_from_name(&ranker, &scorer_options));
+    let query_options = QueryOptions {
+        max_expansions: exit_on_error(take_number(
+            &mut args,
+            "--expansions",
+            QueryOptions::default().max_expansions,
+        )),
+        fuzzy_fallback: !take_flag(&mut args, "--exact"),
+    };
+
+    let format = if take_flag(&mut args, "--json") {
+        OutputFormat::Json
+    } else if io::stdout().is_terminal() {
+        OutputFormat::Colour
+    } else {
+        OutputFormat::Plain
+    };
+    let result_options = ResultOptions {
+        by_document: take_flag(&mut args, "--by-document"),
+        snippets: exit_on_error(take_number(
+            &mut args,
+            "--snippets",
+            ResultOptions::default().snippets,
+        )),
+        format,
+        explain: take_flag(&mut args, "--explain"),
+        ..ResultOptions::default()
+    };
+
+    let analyzer: Option<Analyzer> = exit_on_error(take_analyzer(&mut args));
+    let splitting: Option<Splitting> = exit_on_error(take_splitting(&mut args));
+
+    match args.get(1).map(String::as_str) {
+        Some("index") => {
+            // Use the directory supplied in arguments, otherwise use the directory in which the
+            // program is run.
+            let dir_name = args.get(2).map_or(".", String::as_str);
+            let index_path = args
+                .get(3)
+                .cloned()
+                .unwrap_or_else(|| default_index_path(dir_name));
+
+            let model = if Path::new(&index_path).exists() {
+                update_model(dir_name, &index_path, analyzer, splitting)
+            } else {
+                build_model(
+                    dir_name,
+                    analyzer.unwrap_or_default(),
+                    splitting.unwrap_or_default(),
+                )
+            };
+
+            println!("Writing index to {index_path}.");
+            exit_on_error(storage::save_index(&model, &index_path));
+        }
+        Some("search") => {
+            let target = args.get(2).map_or(".", String::as_str);
+            let (index_path, dir_name) = if Path::new(target).is_dir() {
+                (default_index_path(target), Some(target.to_string()))
+            } else {
+                (target.to_string(), None)
+            };
+
+            println!("Loading index {index_path}.");
+            let mut model = exit_on_error(storage::load_index(&index_path));
+            // An index file named on its own belongs to the directory it was built from.
+            let dir_name = dir_name.unwrap_or_else(|| model.root.clone());
+
+            if watch_files {
+                // The index may be older than the files it will now be kept in step with.
+                loading::sync_directory(&mut model, &dir_name);
+            }
+            search(
+                model,
+                &dir_name,
+                watch_files,
+                scorer.as_ref(),
+                &query_options,
+                &result_options,
+            );
+        }
+        dir_arg => {
+            let dir_name = dir_arg.unwrap_or(".");
+            let model = build_model(
+                dir_name,
+                analyzer.unwrap_or_default(),
+                splitting.unwrap_or_default(),
+            );
+            search(
+                model,
+                dir_name,
+                watch_files,
+                scorer.as_ref(),
+                &query_options,
+                &result_options,
+            );
+        }
+    }
+}
+
+/// Remove a flag from the arguments, returning whether it was present.
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    let found = args.iter().any(|arg| arg == name);
+    args.retain(|arg| arg != name);
+    found
+}
+
+/// Remove an option and its value from the arguments, returning the value.
+fn take_option(args: &mut Vec<String>, name: &str) -> Result<Option<String>, String> {
+    let Some(i) = args.iter().position(|arg| arg == name) else {
+        return Ok(None);
+    };
+
+    if i + 1 >= args.len() {
+        return Err(format!("Missing value for {name}."));
+    }
+
+    let value = args.remove(i + 1);
+    args.remove(i);
+    Ok(Some(value))
+}
+
+/// Remove a numeric option from the arguments, returning its value or the default.
+fn take_number<T: FromStr>(args: &mut Vec<String>, name: &str, default: T) -> Result<T, String> {
+    match take_option(args, name)? {
+        Some(value) => value
+            .parse::<T>()
+            .map_err(|_| format!("Invalid value for {name}: '{value}'.")),
+        None => Ok(default),
+    }
+}
+
+/// Remove the options choosing how the text of documents is analysed, returning the analyzer
+/// they describe, if any.
+fn take_analyzer(args: &mut Vec<String>) -> Result<Option<Analyzer>, String> {
+    let stem = take_option(args, "--stem")?;
+    let fold = take_flag(args, "--fold");
+    let config = take_option(args, "--analyzer")?;
+
+    if let Some(path) = config {
+        if stem.is_some() || fold {
+            return Err(String::from(
+                "Use either --analyzer or --stem and --fold, not both (an analyzer config can \
+                 have 'filter ascii_folding' and 'filter stem <language>' lines).",
+            ));
+        }
+        return Analyzer::from_file(&path).map(Some);
+    }
+    if stem.is_none() && !fold {
+        return Ok(None);
+    }
 
-    let dir_name = if args.len() == 2 { &args[1] } else { "." };
+    let mut analyzer = Analyzer::default();
+    if fold {
+        analyzer = analyzer.with_token_filter(Box::new(AsciiFolding));
+    }
+    if let Some(name) = stem {
+        if let Some(language) = text_process::stemming_from_name(&name)? {
+            analyzer = analyzer.with_token_filter(Box::new(Stem { language }));
+        }
+    }
+    Ok(Some(analyzer))
+}
+
+/// Remove the options choosing how files are split into passages, returning the splitting they
+/// describe, if any.
+fn take_splitting(args: &mut Vec<String>) -> Result<Option<Splitting>, String> {
+    let name = take_option(args, "--passages")?;
+    let size = take_number(args, "--window", 200)?;
+    let overlap = take_number(args, "--overlap", 50)?;
 
-    let path_list: Vec<String> = match loading::load_paths(dir_name) {
+    name.map(|name| Splitting::from_name(&name, size, overlap))
+        .transpose()
+}
+
+/// Print an error and end the program, or unwrap the result.
+fn exit_on_error<T, E: std::fmt::Display>(result: Result<T, E>) -> T {
+    match result {
         Ok(res) => res,
         Err(err) => {
             println!("{err}");
             std::process::exit(1)
         }
-    };
+    }
+}
+
+fn default_index_path(dir_name: &str) -> String {
+    Path::new(dir_name)
+        .join(storage::DEFAULT_INDEX_NAME)
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Load the files below a directory and build a model from them.
+fn build_model(dir_name: &str, analyzer: Analyzer, splitting: Splitting) -> Model {
+    let path_list: Vec<String> = exit_on_error(loading::load_paths(dir_name));
 
     println!("Loading files.");
 
     // Loading data
-    let corpus: Vec<Vec<String>> = match loading::load_corpus(&path_list) {
-        Ok(res) => res,
+    let (documents, corpus) = exit_on_error(loading::load_corpus(&path_list, &analyzer, splitting));
+
+    println!("Creating model.");
+    let mut model = Model::new(documents, &corpus);
+    model.analyzer = analyzer;
+    model.splitting = splitting;
+    model.root = dir_name.to_string();
+    model
+}
+
+/// Bring an existing index up to date rather than rebuilding it from scratch, unless a different
+/// analyzer or splitting into passages is asked for. Without one, the index keeps its own.
+fn update_model(
+    dir_name: &str,
+    index_path: &str,
+    analyzer: Option<Analyzer>,
+    splitting: Option<Splitting>,
+) -> Model {
+    match storage::load_index(index_path) {
+        Ok(model)
+            if analyzer
+                .as_ref()
+                .is_some_and(|analyzer| *analyzer != model.analyzer) =>
+        {
+            println!("The index was built with a different analyzer; rebuilding it.");
+            build_model(
+                dir_name,
+                analyzer.unwrap_or_default(),
+                splitting.unwrap_or(model.splitting),
+            )
+        }
+        Ok(model) if splitting.is_some_and(|splitting| splitting != model.splitting) => {
+            println!("The index was built with different passages; rebuilding it.");
+            build_model(dir_name, model.analyzer, splitting.unwrap_or_default())
+        }
+        Ok(mut model) => {
+            println!("Updating index {index_path}.");
+            let summary = loading::sync_directory(&mut model, dir_name);
+            model.root = dir_name.to_string();
+            println!(
+                "{} added, {} modified, {} removed, {} unchanged.",
+                summary.added, summary.modified, summary.removed, summary.unchanged
+            );
+            model
+        }
         Err(err) => {
-            println!("{err}");
-            std::process::exit(1)
+            println!("{err} Rebuilding the index.");
+            build_model(
+                dir_name,
+                analyzer.unwrap_or_default(),
+                splitting.unwrap_or_default(),
+            )
         }
+    }
+}
+
+/// Search a model, optionally keeping it up to date with the files in its directory.
+fn search(
+    model: Model,
+    dir_name: &str,
+    watch_files: bool,
+    scorer: &dyn Scorer,
+    query_options: &QueryOptions,
+    result_options: &ResultOptions,
+) {
+    let model = Arc::new(RwLock::new(model));
+
+    // The watcher stops when dropped, so it is kept until the search loop ends.
+    let _watcher = if watch_files {
+        println!("Watching {dir_name} for changes.");
+        Some(exit_on_error(watch::watch_directory(
+            Arc::clone(&model),
+            dir_name,
+        )))
+    } else {
+        None
     };
 
-    println!("Creating model.");
-    let model = Model::new(&corpus);
+    search_loop(&model, scorer, query_options, result_options);
+}
+
+/// Repeatedly read queries from the command line and print the best matching documents.
+///
+/// If a query has words which are not in the corpus, a corrected query is suggested, which an
+/// empty line accepts. A query after `:explain` has the scores of its results explained.
+fn search_loop(
+    model: &RwLock<Model>,
+    scorer: &dyn Scorer,
+    query_options: &QueryOptions,
+    result_options: &ResultOptions,
+) {
+    let mut suggestion: Option<String> = None;
+    let explain_options = ResultOptions {
+        explain: true,
+        ..result_options.clone()
+    };
 
     loop {
         // Parse user input.
@@ -48,33 +381,49 @@ fn main() {
         let mut line: String = String::new();
         let input = std::io::stdin().read_line(&mut line);
 
-        if let Err(e) = input {
-            println!("Error reading input, please try again. ({e})");
-            continue;
+        match input {
+            // End of input.
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) => {
+                println!("Error reading input, please try again. ({e})");
+                continue;
+            }
         };
 
-        // Process the input
-        let cleaned_line: String = text_process::clean_up_text(&line);
-        let test_input: Result<Vec<String>, _> = text_process::tokenise(&cleaned_line);
-
-        match test_input {
-            Ok(i) => {
-                if i.contains(&String::from("quit")) {
-                    println!("'quit' detected, ending program.");
-                    break;
-                };
-
-                loading::process_input(&i, &model.words, &model).map_or_else(
-                    |_| {
-                        println!("Input processing failed.");
-                    },
-                    |res| {
-                        calculations::score_query(&res, &model, &path_list, 10);
-                    },
-                );
+        if line.trim().is_empty() {
+            if let Some(accepted) = suggestion.take() {
+                println!("Searching for: {accepted}");
+                line = accepted;
             }
+        }
+
+        let cleaned_line: String = text_process::clean_up_text(&line);
+        if text_process::tokenise(&cleaned_line).is_ok_and(|i| i.contains(&String::from("quit"))) {
+            println!("'quit' detected, ending program.");
+            break;
+        };
 
-            Err(_) => println!("Error: no input (stop words were stripped from the query)"),
+        let (line, options) = match line.trim_start().strip_prefix(":explain") {
+            Some(rest) => (rest.to_string(), &explain_options),
+            None => (line, result_options),
         };
+
+        // Process the input
+        let model = model.read().unwrap_or_else(PoisonError::into_inner);
+        match query::parse_query(&line, &model, query_options) {
+            Ok(res) => calculations::score_query(&res, &model, scorer, options),
+            Err(err) => println!("Error: {err}"),
+        };
+
+        suggestion = query::suggest_correction(&line, &model).map(|suggested| {
+            println!("Did you mean: {suggested}? (Press Enter to search for it.)");
+            // Accepting the correction explains its results if the query asked for that.
+            if options.explain {
+                format!(":explain {suggested}")
+            } else {
+                suggested
+            }
+        });
     }
 }
@@ -0,0 +1,277 @@
+use super::document::{Date, Document, Value};
+use std::cmp::Ordering;
+
+/// How the value of a key of the front matter is compared with the value of a filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    /// The operator written in front of the value of a filter, e.g. `>=` in `date:>=2025-01-01`.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Equal => "",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+        }
+    }
+
+    /// Read the operator at the start of the value of a filter, returning the comparison and the
+    /// rest of the value.
+    pub fn split(value: &str) -> (Comparison, &str) {
+        [
+            Comparison::LessOrEqual,
+            Comparison::GreaterOrEqual,
+            Comparison::Less,
+            Comparison::Greater,
+        ]
+        .into_iter()
+        .find_map(|comparison| {
+            value
+                .strip_prefix(comparison.symbol())
+                .map(|rest| (comparison, rest))
+        })
+        .unwrap_or((Comparison::Equal, value))
+    }
+
+    fn accepts(self, ordering: Ordering) -> bool {
+        match self {
+            Comparison::Equal => ordering == Ordering::Equal,
+            Comparison::Less => ordering == Ordering::Less,
+            Comparison::LessOrEqual => ordering != Ordering::Greater,
+            Comparison::Greater => ordering == Ordering::Greater,
+            Comparison::GreaterOrEqual => ordering != Ordering::Less,
+        }
+    }
+}
+
+/// A condition on a key of the front matter, e.g. `status:draft` or `date:>=2025-01-01`.
+///
+/// Dates and numbers are compared as such, other text ignoring case. A list matches if any of
+/// its items does, and a document without the key never matches.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    pub key: String,
+    pub comparison: Comparison,
+    pub value: String,
+}
+
+impl Filter {
+    /// Make a filter from a key and a value which may start with a comparison operator.
+    pub fn new(key: &str, value: &str) -> Filter {
+        let (comparison, value) = Comparison::split(value);
+        Filter {
+            key: key.to_string(),
+            comparison,
+            value: value.to_string(),
+        }
+    }
+
+    pub fn matches(&self, document: &Document) -> bool {
+        match document.get(&self.key) {
+            Some(Value::List(items)) => items.iter().any(|item| self.accepts(&Value::typed(item))),
+            Some(value) => self.accepts(value),
+            None => false,
+        }
+    }
+
+    fn accepts(&self, value: &Value) -> bool {
+        let ordering = match value {
+            Value::Date(date) => Date::parse(&self.value).map(|other| date.cmp(&other)),
+            Value::Number(number) => self
+                .value
+                .parse::<f64>()
+                .ok()
+                .and_then(|other| number.partial_cmp(&other)),
+            value => Some(
+                value
+                    .to_string()
+                    .to_lowercase()
+                    .cmp(&self.value.to_lowercase()),
+            ),
+        };
+        ordering.is_some_and(|ordering| self.comparison.accepts(ordering))
+    }
+}
+
+impl std::fmt::Display for Filter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}{}", self.key, self.comparison.symbol(), self.value)
+    }
+}
+
+/// A key of the front matter to order results by, e.g. `sort:date` or `sort:-date` for the
+/// latest first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortKey {
+    pub key: String,
+    pub descending: bool,
+}
+
+impl SortKey {
+    /// Read a sort key, written with a `-` in front to sort in descending order.
+    pub fn new(key: &str) -> SortKey {
+        match key.strip_prefix('-') {
+            Some(key) => SortKey {
+                key: key.to_string(),
+                descending: true,
+            },
+            None => SortKey {
+                key: key.to_string(),
+                descending: false,
+            },
+        }
+    }
+
+    /// Order two documents by the value of the key. Documents without it come last either way.
+    pub fn compare(&self, a: &Document, b: &Document) -> Ordering {
+        match (a.get(&self.key), b.get(&self.key)) {
+            (Some(a), Some(b)) => {
+                let ordering = compare_values(a, b);
+                if self.descending {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            }
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    }
+}
+
+impl std::fmt::Display for SortKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sign = if self.descending { "-" } else { "" };
+        write!(f, "sort:{sign}{}", self.key)
+    }
+}
+
+fn compare_values(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Date(a), Value::Date(b)) => a.cmp(b),
+        (Value::Number(a), Value::Number(b)) => a.total_cmp(b),
+        (Value::Text(_), Value::Text(_)) | (Value::List(_), Value::List(_)) => a
+            .to_string()
+            .to_lowercase()
+            .cmp(&b.to_string().to_lowercase()),
+        (a, b) => value_rank(a).cmp(&value_rank(b)),
+    }
+}
+
+/// Where values of each type come when a key holds values of different types: numbers, then
+/// dates, then text, then lists.
+fn value_rank(value: &Value) -> u8 {
+    match value {
+        Value::Number(_) => 0,
+        Value::Date(_) => 1,
+        Value::Text(_) => 2,
+        Value::List(_) => 3,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_document() -> Document {
+        Document::parse(
+            "---\ndate: 2025-03-01\npriority: 2\nstatus: Draft\n\
+            authors: [Ada Lovelace, Alan Turing]\n---\n",
+        )
+    }
+
+    #[test]
+    fn test_filter_matches() {
+        let document = test_document();
+        let matches = |key, value| Filter::new(key, value).matches(&document);
+
+        assert!(matches("status", "draft"));
+        assert!(!matches("status", "published"));
+        assert!(matches("date", ">=2025-03-01"));
+        assert!(matches("date", ">2025-02-28"));
+        assert!(!matches("date", "<2025-03-01"));
+        // Dates are compared as dates, not text, and a value which is not one never matches.
+        assert!(!matches("date", ">2025-3-1"));
+        assert!(matches("priority", "<=2"));
+        assert!(!matches("priority", ">2.5"));
+        assert!(matches("authors", "alan turing"));
+        assert!(matches("Status", "draft"));
+        assert!(!matches("tags", "draft"));
+
+        assert_eq!(
+            Filter::new("date", ">=2025-03-01").to_string(),
+            "date:>=2025-03-01"
+        );
+    }
+
+    #[test]
+    fn test_sort_key() {
+        let earlier = Document::parse("---\ndate: 2024-12-31\n---\n");
+        let later = test_document();
+        let undated = Document::parse("# No front matter\n");
+
+        let ascending = SortKey::new("date");
+        assert_eq!(ascending.compare(&earlier, &later), Ordering::Less);
+        assert_eq!(ascending.compare(&undated, &later), Ordering::Greater);
+
+        let descending = SortKey::new("-date");
+        assert!(descending.descending);
+        assert_eq!(descending.compare(&earlier, &later), Ordering::Greater);
+        assert_eq!(descending.compare(&undated, &later), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_sort_key_mixed_types() {
+        let values = [
+            "draft",
+            "2025-03-01",
+            "10",
+            "[b, a]",
+            "2024-12-31",
+            "9",
+            "Alpha",
+        ];
+        let mut documents: Vec<Document> = values
+            .iter()
+            .map(|value| Document::parse(&format!("---\nkey: {value}\n---\n")))
+            .collect();
+        let key = SortKey::new("key");
+
+        // Values of one type are ordered among themselves, and the types by number, date, text
+        // and list, so the order does not depend on where the documents started.
+        documents.sort_by(|a, b| key.compare(a, b));
+        let sorted: Vec<String> = documents
+            .iter()
+            .map(|document| document.get("key").unwrap().to_string())
+            .collect();
+        assert_eq!(
+            sorted,
+            [
+                "9",
+                "10",
+                "2024-12-31",
+                "2025-03-01",
+                "Alpha",
+                "draft",
+                "b, a"
+            ]
+        );
+
+        documents.reverse();
+        documents.sort_by(|a, b| key.compare(a, b));
+        let resorted: Vec<String> = documents
+            .iter()
+            .map(|document| document.get("key").unwrap().to_string())
+            .collect();
+        assert_eq!(resorted, sorted);
+    }
+}
@@ -1,29 +1,263 @@
 use super::calculations;
-use super::text_process;
-use std::collections::HashMap;
+use super::document::Document;
+use super::passages::{Passage, Splitting};
+use super::text_process::{Analyzer, Token};
+use super::vocabulary::Vocabulary;
 
+/// An entry in a postings list: a document containing a term, the term's frequency in it and the
+/// (ascending) positions at which it occurs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Posting {
+    pub doc: usize,
+    pub tf: f64,
+    pub positions: Vec<u32>,
+}
+
+/// An entry in the document table: where a document came from, its size in tokens and its
+/// structure. When files are split into passages, each passage has an entry of its own.
+///
+/// The modification time, size and content hash of the file are kept so that an index can be
+/// brought up to date by re-reading only the files which have changed.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DocumentInfo {
+    pub path: String,
+    /// Number of tokens in the document, in all of its fields.
+    pub length: u32,
+    /// Modification time of the file, in nanoseconds since the Unix epoch.
+    pub mtime: u64,
+    /// Size of the file in bytes.
+    pub size: u64,
+    pub hash: u64,
+    /// The title, headings, links and front matter of the document. A passage has the title and
+    /// front matter of its file, and its own headings and links.
+    pub metadata: Document,
+    /// Where the document is in its file, if files are split into passages.
+    pub passage: Option<Passage>,
+}
+
+impl DocumentInfo {
+    /// Where to find the document: its path, or for a passage `path:line — section`.
+    pub fn location(&self) -> String {
+        match &self.passage {
+            None => self.path.clone(),
+            Some(Passage {
+                start_line,
+                section: Some(section),
+                ..
+            }) => format!("{}:{start_line} — {section}", self.path),
+            Some(passage) => format!("{}:{}", self.path, passage.start_line),
+        }
+    }
+}
+
+/// An inverted index over a corpus.
+///
+/// Each term maps to the (sparse) list of documents it occurs in, so the model only grows with
+/// the number of term occurrences rather than with documents × vocabulary. `postings` and `idf`
+/// are indexed by the term ids of `vocabulary`; `documents` and `norms` by document id.
 pub struct Model {
-    pub tf: Vec<Vec<f64>>,
+    pub documents: Vec<DocumentInfo>,
+    pub vocabulary: Vocabulary,
+    pub postings: Vec<Vec<Posting>>,
     pub idf: Vec<f64>,
-    pub tf_idf: Vec<Vec<f64>>,
-    pub words: HashMap<String, i32>,
+    pub norms: Vec<f64>,
+    /// Mean length of the documents in tokens.
+    pub average_length: f64,
+    /// How the text of the documents was turned into tokens, which queries must be analysed with
+    /// too.
+    pub analyzer: Analyzer,
+    /// How files were split into documents, which files read again must be split by too.
+    pub splitting: Splitting,
+    /// The directory the files were read from, which the model is kept in step with.
+    pub root: String,
 }
 
 impl Model {
-    pub fn new(corpus: &[Vec<String>]) -> Self {
-        let all_words: HashMap<String, i32> = text_process::get_all_words(corpus);
+    /// Build a model from a corpus and the document table describing it.
+    pub fn new(documents: Vec<DocumentInfo>, corpus: &[Vec<Token>]) -> Self {
+        let vocabulary = Vocabulary::from_corpus(corpus);
 
-        let tf_vals: Vec<Vec<f64>> = calculations::tf_calculation(corpus, &all_words);
+        let postings: Vec<Vec<Posting>> = calculations::tf_calculation(corpus, &vocabulary);
 
-        let idf_vals: Vec<f64> = calculations::idf_calculation(corpus, &all_words);
+        let idf_vals: Vec<f64> = calculations::idf_calculation(corpus.len(), &vocabulary);
 
-        let tf_idf_vals: Vec<Vec<f64>> = calculations::tf_idf_calculation(&tf_vals, &idf_vals);
+        Self::from_parts(documents, vocabulary, postings, idf_vals)
+    }
+
+    /// Assemble a model from previously calculated parts, e.g. those read from an index file.
+    pub fn from_parts(
+        documents: Vec<DocumentInfo>,
+        vocabulary: Vocabulary,
+        postings: Vec<Vec<Posting>>,
+        idf: Vec<f64>,
+    ) -> Self {
+        let norms: Vec<f64> =
+            calculations::document_norms(&postings, &idf, &vocabulary, documents.len());
+        let average_length: f64 = calculations::average_length(&documents);
 
         Self {
-            tf: tf_vals,
-            idf: idf_vals,
-            tf_idf: tf_idf_vals,
-            words: all_words,
+            documents,
+            vocabulary,
+            postings,
+            idf,
+            norms,
+            average_length,
+            analyzer: Analyzer::default(),
+            splitting: Splitting::default(),
+            root: String::from("."),
+        }
+    }
+
+    /// Find the id of the (first) document read from a path.
+    pub fn find_document(&self, path: &str) -> Option<usize> {
+        self.documents.iter().position(|doc| doc.path == path)
+    }
+
+    /// Add a document to the index.
+    ///
+    /// Term statistics change for the whole collection, so `update_statistics` should be called
+    /// once a batch of changes has been made.
+    pub fn add_document(&mut self, info: DocumentInfo, tokens: &[Token]) {
+        let doc = self.documents.len();
+        self.documents.push(info);
+        self.vocabulary.add_document(tokens);
+
+        self.postings.resize(self.vocabulary.len(), Vec::new());
+        // The new document has the largest id, so pushing keeps postings sorted by document.
+        for (id, posting) in calculations::document_postings(doc, tokens, &self.vocabulary) {
+            self.postings[id].push(posting);
+        }
+    }
+
+    /// Remove documents from the index. Later documents move down to fill the gaps, so document
+    /// ids are only stable between changes.
+    ///
+    /// As with `add_document`, `update_statistics` should be called afterwards.
+    pub fn remove_documents(&mut self, docs: &[usize]) {
+        if docs.is_empty() {
+            return;
+        }
+
+        let mut removed: Vec<bool> = vec![false; self.documents.len()];
+        for &doc in docs {
+            removed[doc] = true;
+        }
+
+        // New id of each remaining document.
+        let mut new_ids: Vec<usize> = Vec::with_capacity(removed.len());
+        let mut next = 0;
+        for &gone in &removed {
+            new_ids.push(next);
+            if !gone {
+                next += 1;
+            }
+        }
+
+        for (id, list) in self.postings.iter_mut().enumerate() {
+            for posting in list.iter().filter(|posting| removed[posting.doc]) {
+                self.vocabulary.remove_occurrences(id, posting.tf as u64);
+            }
+            list.retain(|posting| !removed[posting.doc]);
+            for posting in list.iter_mut() {
+                posting.doc = new_ids[posting.doc];
+            }
         }
+
+        let mut doc = 0;
+        self.documents.retain(|_| {
+            doc += 1;
+            !removed[doc - 1]
+        });
+    }
+
+    /// Recalculate the IDF values and document norms after documents have been added or removed.
+    pub fn update_statistics(&mut self) {
+        self.idf = calculations::idf_calculation(self.documents.len(), &self.vocabulary);
+        self.norms = calculations::document_norms(
+            &self.postings,
+            &self.idf,
+            &self.vocabulary,
+            self.documents.len(),
+        );
+        self.average_length = calculations::average_length(&self.documents);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text_process::tokens_from_words;
+
+    fn test_corpus() -> Vec<Vec<Token>> {
+        [
+            vec!["sky".to_string(), "blue".to_string()],
+            vec!["sun".to_string(), "bright".to_string()],
+            vec!["sun".to_string(), "sky".to_string(), "sky".to_string()],
+        ]
+        .iter()
+        .map(|words| tokens_from_words(words))
+        .collect()
+    }
+
+    fn test_model() -> Model {
+        let corpus = test_corpus();
+        let documents: Vec<DocumentInfo> = (1..=3)
+            .map(|i| DocumentInfo {
+                path: format!("doc{i}.txt"),
+                ..Default::default()
+            })
+            .collect();
+        Model::new(documents, &corpus)
+    }
+
+    #[test]
+    fn test_remove_documents() {
+        let mut model = test_model();
+
+        model.remove_documents(&[0]);
+        model.update_statistics();
+
+        let sky = model.vocabulary.id("sky").unwrap();
+        let blue = model.vocabulary.id("blue").unwrap();
+
+        assert_eq!(model.find_document("doc1.txt"), None);
+        assert_eq!(model.find_document("doc3.txt"), Some(1));
+        assert_eq!(
+            model.postings[sky],
+            vec![Posting {
+                doc: 1,
+                tf: 2.0,
+                positions: vec![1, 2]
+            }]
+        );
+        assert_eq!(model.postings[blue], vec![]);
+        assert_eq!(model.vocabulary.doc_freq(sky), 1);
+        assert_eq!(model.vocabulary.collection_freq(sky), 2);
+        assert_eq!(model.vocabulary.doc_freq(blue), 0);
+        assert_eq!(model.idf[sky], 3.0f64.ln());
+    }
+
+    #[test]
+    fn test_add_document_matches_rebuild() {
+        let mut model = test_model();
+        let words = tokens_from_words(&["blue".to_string(), "moon".to_string()]);
+
+        model.add_document(
+            DocumentInfo {
+                path: "doc4.txt".to_string(),
+                ..Default::default()
+            },
+            &words,
+        );
+        model.update_statistics();
+
+        let mut corpus = test_corpus();
+        corpus.push(words);
+        let rebuilt = Model::new(model.documents.clone(), &corpus);
+
+        assert_eq!(model.vocabulary, rebuilt.vocabulary);
+        assert_eq!(model.postings, rebuilt.postings);
+        assert_eq!(model.idf, rebuilt.idf);
+        assert_eq!(model.norms, rebuilt.norms);
     }
 }
@@ -0,0 +1,369 @@
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+use std::ops::Range;
+
+/// How files are split into the units which are indexed and ranked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Splitting {
+    /// Each file is one document.
+    #[default]
+    Document,
+    /// A passage for each heading and the text below it, up to the next heading. Text before the
+    /// first heading is a passage of its own.
+    Heading,
+    /// A passage for each paragraph, list, code block or other top-level block of the markdown,
+    /// with a heading kept with the block after it.
+    Paragraph,
+    /// Passages of whole lines holding at least `size` words, each starting far enough before
+    /// the end of the last to share `overlap` words with it.
+    Window { size: usize, overlap: usize },
+}
+
+impl Splitting {
+    /// Read a splitting from its name (`document`, `heading`, `paragraph` or `window`), with the
+    /// size and overlap of windows in words.
+    pub fn from_name(name: &str, size: usize, overlap: usize) -> Result<Splitting, String> {
+        match name {
+            "document" => Ok(Splitting::Document),
+            "heading" => Ok(Splitting::Heading),
+            "paragraph" => Ok(Splitting::Paragraph),
+            "window" if size == 0 => Err(String::from("Windows must hold at least one word.")),
+            "window" if overlap >= size => Err(format!(
+                "The overlap of windows ({overlap}) must be less than their size ({size})."
+            )),
+            "window" => Ok(Splitting::Window { size, overlap }),
+            _ => Err(format!(
+                "Unknown passages '{name}' (expected document, heading, paragraph or window)."
+            )),
+        }
+    }
+
+    /// How the splitting is written in an index file, e.g. `window 200 50`.
+    pub fn config(&self) -> String {
+        match self {
+            Splitting::Document => String::from("document"),
+            Splitting::Heading => String::from("heading"),
+            Splitting::Paragraph => String::from("paragraph"),
+            Splitting::Window { size, overlap } => format!("window {size} {overlap}"),
+        }
+    }
+
+    /// Read a splitting back from its config.
+    pub fn from_config(config: &str) -> Result<Splitting, String> {
+        let parts: Vec<&str> = config.split_whitespace().collect();
+        match parts.as_slice() {
+            ["window", size, overlap] => {
+                let number = |text: &str| {
+                    text.parse::<usize>()
+                        .map_err(|_| format!("Invalid passages '{config}'."))
+                };
+                Splitting::from_name("window", number(size)?, number(overlap)?)
+            }
+            [name] if *name != "window" => Splitting::from_name(name, 0, 0),
+            _ => Err(format!("Invalid passages '{config}'.")),
+        }
+    }
+}
+
+/// Where a passage is in its file.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Passage {
+    /// The first and last lines of the passage (1-based, inclusive).
+    pub start_line: u32,
+    pub end_line: u32,
+    /// The heading of the section the passage is in, if any.
+    pub section: Option<String>,
+}
+
+/// The lines of a file and the markdown structure found in them.
+struct Lines<'a> {
+    contents: &'a str,
+    /// The byte offset at which each line starts.
+    starts: Vec<usize>,
+    /// The first line after the front matter.
+    body: usize,
+    /// The first line of each heading, with its text.
+    headings: Vec<(usize, String)>,
+    /// The lines of each top-level block other than front matter, and whether it is a heading.
+    blocks: Vec<(Range<usize>, bool)>,
+}
+
+impl<'a> Lines<'a> {
+    fn new(contents: &'a str) -> Self {
+        let starts: Vec<usize> = std::iter::once(0)
+            .chain(contents.match_indices('\n').map(|(i, _)| i + 1))
+            .filter(|&start| start < contents.len())
+            .collect();
+        let mut lines = Lines {
+            contents,
+            starts,
+            body: 0,
+            headings: Vec::new(),
+            blocks: Vec::new(),
+        };
+
+        let options = Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
+            | Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS;
+        let mut depth = 0;
+        let mut heading: Option<(usize, String)> = None;
+
+        for (event, range) in Parser::new_ext(contents, options).into_offset_iter() {
+            match event {
+                Event::Start(tag) => {
+                    let first = lines.line_of(range.start);
+                    let last = lines.line_of(range.end.saturating_sub(1).max(range.start));
+                    match tag {
+                        Tag::MetadataBlock(_) => lines.body = last + 1,
+                        Tag::Heading { .. } => heading = Some((first, String::new())),
+                        _ => {}
+                    }
+                    if depth == 0 && !matches!(tag, Tag::MetadataBlock(_)) {
+                        let is_heading = matches!(tag, Tag::Heading { .. });
+                        lines.blocks.push((first..last + 1, is_heading));
+                    }
+                    depth += 1;
+                }
+                Event::End(tag) => {
+                    depth -= 1;
+                    if matches!(tag, TagEnd::Heading(_)) {
+                        if let Some((line, text)) = heading.take() {
+                            lines.headings.push((line, text.trim().to_string()));
+                        }
+                    }
+                }
+                Event::Text(text) | Event::Code(text) => {
+                    if let Some((_, buffer)) = &mut heading {
+                        buffer.push_str(&text);
+                    }
+                }
+                Event::SoftBreak | Event::HardBreak => {
+                    if let Some((_, buffer)) = &mut heading {
+                        buffer.push(' ');
+                    }
+                }
+                // Rules, HTML and the like outside any block.
+                _ if depth == 0 => {
+                    let first = lines.line_of(range.start);
+                    let last = lines.line_of(range.end.saturating_sub(1).max(range.start));
+                    lines.blocks.push((first..last + 1, false));
+                }
+                _ => {}
+            }
+        }
+
+        lines
+    }
+
+    fn len(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// The (0-based) line a byte is on.
+    fn line_of(&self, offset: usize) -> usize {
+        self.starts
+            .partition_point(|&start| start <= offset)
+            .saturating_sub(1)
+    }
+
+    fn text(&self, lines: Range<usize>) -> &'a str {
+        let end = self
+            .starts
+            .get(lines.end)
+            .copied()
+            .unwrap_or(self.contents.len());
+        &self.contents[self.starts[lines.start]..end]
+    }
+
+    fn is_blank(&self, line: usize) -> bool {
+        self.text(line..line + 1).trim().is_empty()
+    }
+
+    /// The heading of the section a line is in.
+    fn section(&self, line: usize) -> Option<String> {
+        self.headings
+            .iter()
+            .take_while(|(start, _)| *start <= line)
+            .last()
+            .map(|(_, text)| text.clone())
+            .filter(|text| !text.is_empty())
+    }
+
+    /// The line ranges of the passages of a splitting, before blank lines are trimmed.
+    fn ranges(&self, splitting: Splitting) -> Vec<Range<usize>> {
+        let (body, n) = (self.body, self.len());
+
+        match splitting {
+            Splitting::Document => std::iter::once(body..n).collect(),
+            Splitting::Heading => {
+                let mut starts: Vec<usize> = vec![body];
+                starts.extend(
+                    self.headings
+                        .iter()
+                        .map(|&(line, _)| line)
+                        .filter(|&line| line > body),
+                );
+                starts
+                    .iter()
+                    .zip(starts.iter().skip(1).chain([&n]))
+                    .map(|(&start, &end)| start..end)
+                    .collect()
+            }
+            Splitting::Paragraph => {
+                let mut ranges: Vec<Range<usize>> = Vec::new();
+                let mut heading: Option<usize> = None;
+                for (lines, is_heading) in &self.blocks {
+                    if *is_heading {
+                        heading = heading.or(Some(lines.start));
+                        continue;
+                    }
+                    ranges.push(heading.take().unwrap_or(lines.start)..lines.end);
+                }
+                // A heading at the end of the file with nothing after it.
+                ranges.extend(heading.map(|start| start..n));
+                ranges
+            }
+            Splitting::Window { size, overlap } => {
+                let words: Vec<usize> = (0..n)
+                    .map(|line| self.text(line..line + 1).split_whitespace().count())
+                    .collect();
+                let mut ranges: Vec<Range<usize>> = Vec::new();
+                let mut start = body;
+                while start < n {
+                    let mut end = start + 1;
+                    let mut count = words[start];
+                    while count < size && end < n {
+                        count += words[end];
+                        end += 1;
+                    }
+                    ranges.push(start..end);
+                    if end == n {
+                        break;
+                    }
+
+                    // Go back over the last lines of the window until they hold enough words to
+                    // overlap, always moving on by at least a line.
+                    let mut next = end;
+                    let mut shared = 0;
+                    while shared < overlap && next > start + 1 {
+                        next -= 1;
+                        shared += words[next];
+                    }
+                    start = next;
+                }
+                ranges
+            }
+        }
+    }
+}
+
+/// Split the contents of a file into passages, each with its text. Front matter is left out,
+/// as are passages with nothing but blank lines. Each line of a window passage goes whole into
+/// it, so windows may hold a few more words than their size.
+pub fn split_passages(contents: &str, splitting: Splitting) -> Vec<(Passage, &str)> {
+    let lines = Lines::new(contents);
+
+    lines
+        .ranges(splitting)
+        .into_iter()
+        .filter_map(|Range { mut start, mut end }| {
+            while start < end && lines.is_blank(start) {
+                start += 1;
+            }
+            while end > start && lines.is_blank(end - 1) {
+                end -= 1;
+            }
+            (start < end).then(|| {
+                let passage = Passage {
+                    start_line: start as u32 + 1,
+                    end_line: end as u32,
+                    section: lines.section(start),
+                };
+                (passage, lines.text(start..end))
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTES: &str = "---\ntitle: Rust notes\n---\nIntro line.\n\n# Ownership\n\n\
+        Each value has one owner.\n\n- moves\n- copies\n\n## Borrowing\n\n```rust\n# not a heading\n\
+        let r = &x;\n```\n";
+
+    fn lines<'a>(passages: &'a [(Passage, &str)]) -> Vec<(u32, u32, Option<&'a str>)> {
+        passages
+            .iter()
+            .map(|(passage, _)| {
+                (
+                    passage.start_line,
+                    passage.end_line,
+                    passage.section.as_deref(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_split_by_heading() {
+        let result = split_passages(NOTES, Splitting::Heading);
+
+        assert_eq!(
+            lines(&result),
+            vec![
+                (4, 4, None),
+                (6, 11, Some("Ownership")),
+                (13, 18, Some("Borrowing")),
+            ]
+        );
+        assert_eq!(result[0].1, "Intro line.\n");
+        assert!(result[2].1.starts_with("## Borrowing\n"));
+    }
+
+    #[test]
+    fn test_split_by_paragraph() {
+        let result = split_passages(NOTES, Splitting::Paragraph);
+
+        assert_eq!(
+            lines(&result),
+            vec![
+                (4, 4, None),
+                (6, 8, Some("Ownership")),
+                (10, 11, Some("Ownership")),
+                (13, 18, Some("Borrowing")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_by_window() {
+        let contents = "one two\nthree four\nfive six\nseven eight\nnine\n";
+
+        let result = split_passages(
+            contents,
+            Splitting::Window {
+                size: 4,
+                overlap: 2,
+            },
+        );
+
+        // Each window starts at the last line of the one before, which holds two words.
+        assert_eq!(
+            lines(&result),
+            vec![(1, 2, None), (2, 3, None), (3, 4, None), (4, 5, None)]
+        );
+
+        let result = split_passages(NOTES, Splitting::Document);
+        assert_eq!(lines(&result), vec![(4, 18, None)]);
+    }
+
+    #[test]
+    fn test_splitting_config() {
+        let window = Splitting::from_name("window", 200, 50).unwrap();
+
+        assert_eq!(Splitting::from_config(&window.config()), Ok(window));
+        assert_eq!(Splitting::from_config("heading"), Ok(Splitting::Heading));
+        assert!(Splitting::from_name("window", 10, 10).is_err());
+        assert!(Splitting::from_name("chapter", 0, 0).is_err());
+    }
+}
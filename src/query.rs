@@ -0,0 +1,1852 @@
+use super::fields::Field;
+use super::metadata::{Comparison, Filter, SortKey};
+use super::model::{Model, Posting};
+use super::scoring::TermStats;
+use super::text_process::{Analyzer, Token};
+use super::vocabulary::TermId;
+use std::collections::{BTreeMap, BTreeSet};
+use unicode_normalization::UnicodeNormalization;
+
+/// A part of a query which is scored against the documents: a term, or a phrase scored as if it
+/// were a single term.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryTerm {
+    pub text: String,
+    /// Number of times the term appears in the query.
+    pub weight: f64,
+    pub stats: TermStats,
+    /// The documents matched, with the frequency and positions of the term in each, ordered by
+    /// document. The positions of a phrase are those at which it starts.
+    pub matches: Vec<Posting>,
+    /// The terms of the vocabulary the term is made of, with what each occurrence counts for
+    /// (e.g. less for a fuzzy match) and the boost of its field. Phrases have none, as they are
+    /// matched word by word.
+    pub sources: Vec<(TermId, f64, f64)>,
+}
+
+impl QueryTerm {
+    /// A single term of the vocabulary.
+    pub fn from_term(model: &Model, id: TermId) -> Self {
+        Self {
+            text: model.vocabulary.term(id).to_string(),
+            weight: 1.0,
+            stats: TermStats {
+                doc_freq: model.vocabulary.doc_freq(id),
+                collection_freq: model.vocabulary.collection_freq(id),
+            },
+            matches: model.postings[id].clone(),
+            sources: vec![(id, 1.0, 1.0)],
+        }
+    }
+
+    /// A word in any field, with its occurrences in each field weighted by the boost of the field
+    /// (see `Field::boost`), or `None` if the word is not in the corpus. Only the positions of the
+    /// word in the body are kept.
+    pub fn from_word(model: &Model, word: &str) -> Option<Self> {
+        let terms: Vec<(TermId, f64, f64, bool)> = word_in_fields(model, word)
+            .map(|(id, boost, body)| (id, 1.0, boost, body))
+            .collect();
+        if terms.is_empty() {
+            return None;
+        }
+
+        Some(Self::merged(model, word.to_string(), &terms))
+    }
+
+    /// Several terms of the vocabulary scored as if they were one word: the frequency of the
+    /// word in a document is the sum of the frequencies of the terms, each times its weight and
+    /// the boost of its field. The positions of the terms which are marked are kept.
+    pub fn merged(model: &Model, text: String, terms: &[(TermId, f64, f64, bool)]) -> Self {
+        let mut matches: BTreeMap<usize, Posting> = BTreeMap::new();
+
+        for &(id, weight, boost, keep_positions) in terms {
+            for posting in &model.postings[id] {
+                let merged = matches.entry(posting.doc).or_insert_with(|| Posting {
+                    doc: posting.doc,
+                    tf: 0.0,
+                    positions: Vec::new(),
+                });
+                merged.tf += weight * boost * posting.tf;
+                if keep_positions {
+                    merged.positions.extend(&posting.positions);
+                }
+            }
+        }
+
+        let matches: Vec<Posting> = matches
+            .into_values()
+            .map(|mut posting| {
+                posting.positions.sort_unstable();
+                posting
+            })
+            .collect();
+
+        Self {
+            text,
+            weight: 1.0,
+            stats: TermStats {
+                doc_freq: matches.len() as u32,
+                collection_freq: terms
+                    .iter()
+                    .map(|&(id, _, _, _)| model.vocabulary.collection_freq(id))
+                    .sum(),
+            },
+            matches,
+            sources: terms
+                .iter()
+                .map(|&(id, weight, boost, _)| (id, weight, boost))
+                .collect(),
+        }
+    }
+
+    /// A phrase, matching documents where its words occur with the same spacing as in the query.
+    /// Statistics for the phrase are gathered from its matches.
+    pub fn from_phrase(model: &Model, tokens: &[Token]) -> Self {
+        let text: String = tokens
+            .iter()
+            .map(|token| token.text.as_str())
+            .collect::<Vec<&str>>()
+            .join(" ");
+
+        let ids: Option<Vec<(TermId, u32)>> = tokens
+            .iter()
+            .map(|token| {
+                model
+                    .vocabulary
+                    .id(&token.text)
+                    .map(|id| (id, token.position))
+            })
+            .collect();
+        // A phrase with a word which is not in the corpus cannot match anything.
+        let matches: Vec<Posting> = ids.map_or(Vec::new(), |ids| phrase_matches(model, &ids));
+
+        Self {
+            text: format!("\"{text}\""),
+            weight: 1.0,
+            stats: TermStats {
+                doc_freq: matches.len() as u32,
+                collection_freq: matches.iter().map(|posting| posting.tf as u64).sum(),
+            },
+            matches,
+            sources: Vec::new(),
+        }
+    }
+
+    /// The frequency of the term in a document, with each occurrence in a field counted for the
+    /// boost of the field, or if `boosted` is false, counted once.
+    pub fn tf(&self, model: &Model, doc: usize, boosted: bool) -> f64 {
+        if boosted || self.sources.is_empty() {
+            return find_posting(&self.matches, doc).map_or(0.0, |posting| posting.tf);
+        }
+
+        self.sources
+            .iter()
+            .filter_map(|&(id, weight, _)| {
+                Some(weight * find_posting(&model.postings[id], doc)?.tf)
+            })
+            .sum()
+    }
+}
+
+/// The vocabulary terms for a word in each field it occurs in, with the boost of the field and
+/// whether it is the body.
+fn word_in_fields<'a>(
+    model: &'a Model,
+    word: &'a str,
+) -> impl Iterator<Item = (TermId, f64, bool)> + 'a {
+    Field::ALL
+        .into_iter()
+        .filter(|field| field.boost() > 0.0)
+        .filter_map(move |field| {
+            let id = model.vocabulary.id(&field.term(word))?;
+            Some((id, field.boost(), field == Field::Body))
+        })
+}
+
+/// The fields to look for a word in: the one it is limited to, or else all those which count
+/// towards ranking.
+fn search_fields(field: Option<Field>) -> Vec<Field> {
+    match field {
+        Some(field) => vec![field],
+        None => Field::ALL
+            .into_iter()
+            .filter(|field| field.boost() > 0.0)
+            .collect(),
+    }
+}
+
+/// The word of a vocabulary term in a field, if the term is in that field and occurs in any
+/// document.
+fn field_word<'a>(model: &Model, field: Field, term: &'a str, id: TermId) -> Option<&'a str> {
+    let word = term.strip_prefix(&field.term(""))?;
+    // Body words never contain a colon, so this skips the words of other fields.
+    (!word.contains(':') && model.vocabulary.doc_freq(id) > 0).then_some(word)
+}
+
+/// Find the words of the corpus matching a wildcard pattern (see `Vocabulary::matching_terms`),
+/// within a field or else in any field. If there are more than `limit`, only the `limit` which
+/// occur in the most documents are kept.
+pub fn expand_wildcard(
+    model: &Model,
+    pattern: &str,
+    field: Option<Field>,
+    limit: usize,
+) -> Vec<String> {
+    let mut doc_freqs: BTreeMap<String, u32> = BTreeMap::new();
+    for field in search_fields(field) {
+        for (term, id) in model.vocabulary.matching_terms(&field.term(pattern)) {
+            if let Some(word) = field_word(model, field, term, id) {
+                *doc_freqs.entry(word.to_string()).or_default() += model.vocabulary.doc_freq(id);
+            }
+        }
+    }
+
+    let mut words: Vec<(String, u32)> = doc_freqs.into_iter().collect();
+    words.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    words.truncate(limit);
+
+    words.into_iter().map(|(word, _)| word).collect()
+}
+
+/// Find the words of the corpus within `max_edits` Damerau-Levenshtein edits of a word, within a
+/// field or else in any field, with their distances. The closest words, and of those the ones
+/// which occur in the most documents, come first, and at most `limit` are kept.
+pub fn expand_fuzzy(
+    model: &Model,
+    word: &str,
+    max_edits: usize,
+    field: Option<Field>,
+    limit: usize,
+) -> Vec<(String, usize)> {
+    // The distance and document frequency of each word.
+    let mut found: BTreeMap<String, (usize, u32)> = BTreeMap::new();
+    for field in search_fields(field) {
+        for (id, distance) in model.vocabulary.similar_terms(&field.term(word), max_edits) {
+            let term = model.vocabulary.term(id);
+            if let Some(word) = field_word(model, field, term, id) {
+                let entry = found.entry(word.to_string()).or_insert((distance, 0));
+                entry.1 += model.vocabulary.doc_freq(id);
+            }
+        }
+    }
+
+    let mut words: Vec<(String, (usize, u32))> = found.into_iter().collect();
+    words.sort_by(|a, b| {
+        (a.1 .0)
+            .cmp(&b.1 .0)
+            .then_with(|| b.1 .1.cmp(&a.1 .1))
+            .then_with(|| a.0.cmp(&b.0))
+    });
+    words.truncate(limit);
+
+    words
+        .into_iter()
+        .map(|(word, (distance, _))| (word, distance))
+        .collect()
+}
+
+/// How many edits to allow when looking for words like one which is not in the corpus: none for
+/// very short words, which are too easily turned into others.
+pub fn automatic_edits(word: &str) -> usize {
+    match word.chars().count() {
+        0..=2 => 0,
+        3..=5 => 1,
+        _ => 2,
+    }
+}
+
+/// The query term for a set of words scored together as one word, each with a weight, within a
+/// field or else in any field.
+fn expanded_term(
+    model: &Model,
+    text: String,
+    words: &[(String, f64)],
+    field: Option<Field>,
+) -> QueryTerm {
+    let terms: Vec<(TermId, f64, f64, bool)> = match field {
+        None => words
+            .iter()
+            .flat_map(|(word, weight)| {
+                word_in_fields(model, word).map(move |(id, boost, body)| (id, *weight, boost, body))
+            })
+            .collect(),
+        Some(field) => words
+            .iter()
+            .filter_map(|(word, weight)| {
+                Some((model.vocabulary.id(&field.term(word))?, *weight, 1.0, true))
+            })
+            .collect(),
+    };
+
+    QueryTerm::merged(model, text, &terms)
+}
+
+/// Find the documents in which the terms of a phrase occur at the same relative positions as in
+/// the phrase, and the positions at which it starts.
+pub fn phrase_matches(model: &Model, phrase: &[(TermId, u32)]) -> Vec<Posting> {
+    let Some(&(first, first_position)) = phrase.first() else {
+        return Vec::new();
+    };
+    let rest: Vec<(TermId, u32)> = phrase[1..]
+        .iter()
+        .map(|&(id, position)| (id, position - first_position))
+        .collect();
+
+    let mut matches: Vec<Posting> = Vec::new();
+
+    for posting in &model.postings[first] {
+        let others: Option<Vec<&Posting>> = rest
+            .iter()
+            .map(|&(id, _)| find_posting(&model.postings[id], posting.doc))
+            .collect();
+        let Some(others) = others else {
+            continue;
+        };
+
+        let starts: Vec<u32> = posting
+            .positions
+            .iter()
+            .copied()
+            .filter(|&start| {
+                rest.iter().zip(&others).all(|(&(_, offset), other)| {
+                    other.positions.binary_search(&(start + offset)).is_ok()
+                })
+            })
+            .collect();
+
+        if !starts.is_empty() {
+            matches.push(Posting {
+                doc: posting.doc,
+                tf: starts.len() as f64,
+                positions: starts,
+            });
+        }
+    }
+
+    matches
+}
+
+/// Find the posting for a document in a postings list.
+pub fn find_posting(list: &[Posting], doc: usize) -> Option<&Posting> {
+    list.binary_search_by_key(&doc, |posting| posting.doc)
+        .ok()
+        .map(|i| &list[i])
+}
+
+/// A query as written, after its words have been normalised like the text of documents.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    Term(String),
+    /// A word with `*` (any run of characters) or `?` (any one character) in it, matching any
+    /// word of the corpus which fits.
+    Wildcard(String),
+    /// A word matching any word of the corpus within a number of edits (`borow~1`).
+    Fuzzy(String, usize),
+    /// Words which must occur with the same spacing as in the query.
+    Phrase(Vec<Token>),
+    /// Documents must match every part.
+    And(Vec<Query>),
+    /// Documents must match at least one part.
+    Or(Vec<Query>),
+    /// Documents must not match the part.
+    Not(Box<Query>),
+    /// Parts written side by side. Documents must match every required part and no excluded
+    /// part, and if nothing is required, at least one of the optional parts.
+    Clauses(Vec<(Occur, Query)>),
+    /// Words and phrases in the part only match in the field. Words which are not limited to a
+    /// field match in any field.
+    Field(Field, Box<Query>),
+    /// Documents whose front matter meets a condition, e.g. `status:draft`. Filters only select
+    /// documents and are not scored.
+    Filter(Filter),
+}
+
+/// How a clause written side by side with others must match.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Occur {
+    Optional,
+    Required,
+    Excluded,
+}
+
+/// A unit of query syntax, as read from the query text.
+#[derive(Debug, Clone, PartialEq)]
+enum Lexeme {
+    Word(String),
+    Phrase(String),
+    And,
+    Or,
+    Not,
+    Plus,
+    Minus,
+    Open,
+    Close,
+    /// A field name and colon, e.g. `title:`, limiting the part which follows to the field.
+    Field(Field),
+    /// Any other key, a colon and a value, e.g. `status:draft`.
+    Filter(Filter),
+    /// `sort:` and a key to order the results by.
+    Sort(SortKey),
+}
+
+impl Lexeme {
+    /// How the lexeme is written, for error messages.
+    fn text(&self) -> String {
+        match self {
+            Lexeme::Word(text) | Lexeme::Phrase(text) => text.clone(),
+            Lexeme::And => String::from("AND"),
+            Lexeme::Or => String::from("OR"),
+            Lexeme::Not => String::from("NOT"),
+            Lexeme::Plus => String::from("+"),
+            Lexeme::Minus => String::from("-"),
+            Lexeme::Open => String::from("("),
+            Lexeme::Close => String::from(")"),
+            Lexeme::Field(field) => format!("{}:", field.name()),
+            Lexeme::Filter(filter) => filter.to_string(),
+            Lexeme::Sort(sort) => sort.to_string(),
+        }
+    }
+}
+
+/// Split a query into lexemes, each with the (1-based) character at which it starts.
+///
+/// `AND`, `OR` and `NOT` are only operators in capitals, and `+` and `-` only at the start of a
+/// word, phrase or group. The name of a field and a colon (e.g. `title:`) at the start of a word,
+/// phrase or group limits it to the field. Any other key and a colon followed by a value is a
+/// filter on the front matter (see `metadata_prefix`).
+fn lex(line: &str) -> Result<Vec<(usize, Lexeme)>, String> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut lexemes: Vec<(usize, Lexeme)> = Vec::new();
+    let mut i = 0;
+
+    let ends_word = |c: char| c.is_whitespace() || matches!(c, '(' | ')' | '"');
+
+    while i < chars.len() {
+        let start = i;
+
+        if let Some(field) = field_prefix(&chars[i..]) {
+            lexemes.push((start + 1, Lexeme::Field(field)));
+            i += field.name().len() + 1;
+            continue;
+        }
+
+        if let Some((length, lexeme)) = metadata_prefix(&chars[i..]) {
+            lexemes.push((start + 1, lexeme));
+            i += length;
+            continue;
+        }
+
+        let lexeme = match chars[i] {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => Lexeme::Open,
+            ')' => Lexeme::Close,
+            '+' | '-' if chars.get(i + 1).is_some_and(|&c| !c.is_whitespace()) => {
+                if chars[i] == '+' {
+                    Lexeme::Plus
+                } else {
+                    Lexeme::Minus
+                }
+            }
+            '"' => {
+                let Some(length) = chars[i + 1..].iter().position(|&c| c == '"') else {
+                    return Err(format!(
+                        "Missing closing quote for the phrase at character {}.",
+                        start + 1
+                    ));
+                };
+                i += length + 1;
+                Lexeme::Phrase(chars[start + 1..i].iter().collect())
+            }
+            _ => {
+                while i + 1 < chars.len() && !ends_word(chars[i + 1]) {
+                    i += 1;
+                }
+                let word: String = chars[start..=i].iter().collect();
+                match word.as_str() {
+                    "AND" => Lexeme::And,
+                    "OR" => Lexeme::Or,
+                    "NOT" => Lexeme::Not,
+                    _ => Lexeme::Word(word),
+                }
+            }
+        };
+
+        lexemes.push((start + 1, lexeme));
+        i += 1;
+    }
+
+    Ok(lexemes)
+}
+
+/// The field named at the start of some text, if it is followed by a colon (e.g. `title:`).
+fn field_prefix(chars: &[char]) -> Option<Field> {
+    Field::ALL.into_iter().find(|field| {
+        let name = field.name();
+        chars.len() > name.len()
+            && chars[..name.len()].iter().copied().eq(name.chars())
+            && chars[name.len()] == ':'
+    })
+}
+
+/// A filter or sort key at the start of some text, with the number of characters it takes up.
+///
+/// The key starts with a letter and is followed by a colon and a value, which may start with a
+/// comparison (`date:>=2025-01-01`) and be quoted (`author:"Ada Lovelace"`). `sort:` and a key,
+/// with `-` in front to sort in descending order, orders the results. Text which only looks like
+/// this, e.g. a URL, is not a filter.
+fn metadata_prefix(chars: &[char]) -> Option<(usize, Lexeme)> {
+    if !chars.first()?.is_alphabetic() {
+        return None;
+    }
+    let colon = chars
+        .iter()
+        .position(|&c| !(c.is_alphanumeric() || matches!(c, '_' | '.' | '-')))?;
+    if chars[colon] != ':' {
+        return None;
+    }
+    let key: String = chars[..colon].iter().collect();
+
+    let rest: String = chars[colon + 1..].iter().collect();
+    let (comparison, rest) = Comparison::split(&rest);
+    let operator = comparison.symbol().len();
+    let value_start = colon + 1 + operator;
+
+    let (value, end): (String, usize) = if rest.starts_with('"') {
+        let length = chars[value_start + 1..].iter().position(|&c| c == '"')?;
+        let value = chars[value_start + 1..value_start + 1 + length]
+            .iter()
+            .collect();
+        (value, value_start + length + 2)
+    } else {
+        let length = chars[value_start..]
+            .iter()
+            .take_while(|&&c| !(c.is_whitespace() || matches!(c, '(' | ')' | '"')))
+            .count();
+        (
+            chars[value_start..value_start + length].iter().collect(),
+            value_start + length,
+        )
+    };
+    if value.is_empty() || value.starts_with([':', '/']) {
+        return None;
+    }
+
+    if key == "sort" {
+        return (operator == 0).then(|| (end, Lexeme::Sort(SortKey::new(&value))));
+    }
+    Some((
+        end,
+        Lexeme::Filter(Filter {
+            key,
+            comparison,
+            value,
+        }),
+    ))
+}
+
+/// Recursive descent parser over the lexemes of a query.
+///
+/// ```text
+/// clauses := (("+" | "-")? or)*
+/// or      := and ("OR" and)*
+/// and     := unary ("AND" unary)*
+/// unary   := ("NOT" | "+" | "-" | field ":") unary | primary
+/// primary := word | phrase | filter | "(" clauses ")"
+/// ```
+///
+/// Sort keys may appear anywhere among the clauses of the query; the last one is kept.
+///
+/// Words which are dropped by normalisation (e.g. stop words) parse to `None`, and are left out
+/// of the parts around them.
+struct Parser<'a> {
+    lexemes: Vec<(usize, Lexeme)>,
+    next: usize,
+    analyzer: &'a Analyzer,
+    sort: Option<SortKey>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Lexeme> {
+        self.lexemes.get(self.next).map(|(_, lexeme)| lexeme)
+    }
+
+    fn advance(&mut self) -> (usize, Lexeme) {
+        self.next += 1;
+        self.lexemes[self.next - 1].clone()
+    }
+
+    fn clauses(&mut self, in_group: bool) -> Result<Option<Query>, String> {
+        let mut clauses: Vec<(Occur, Query)> = Vec::new();
+        let mut found_clause = false;
+
+        loop {
+            match self.lexemes.get(self.next) {
+                None if in_group => return Err(String::from("Missing closing parenthesis.")),
+                None => break,
+                Some((_, Lexeme::Close)) if in_group => break,
+                Some((at, Lexeme::Close)) => {
+                    return Err(format!("Unmatched ')' at character {at}."));
+                }
+                Some((at, lexeme @ (Lexeme::And | Lexeme::Or))) => {
+                    return Err(format!(
+                        "Expected a term before '{}' at character {at}.",
+                        lexeme.text()
+                    ));
+                }
+                Some((_, Lexeme::Sort(sort))) => {
+                    self.sort = Some(sort.clone());
+                    self.advance();
+                    continue;
+                }
+                _ => {}
+            }
+
+            let occur = match self.peek() {
+                Some(Lexeme::Plus) => Occur::Required,
+                Some(Lexeme::Minus) => Occur::Excluded,
+                _ => Occur::Optional,
+            };
+            let after = if occur == Occur::Optional {
+                None
+            } else {
+                Some(self.advance())
+            };
+            // A phrase or filter is required unless it is marked otherwise.
+            let occur = match self.peek() {
+                Some(Lexeme::Phrase(_) | Lexeme::Filter(_)) if after.is_none() => Occur::Required,
+                _ => occur,
+            };
+
+            found_clause = true;
+            match self.or(after)? {
+                // "sky NOT blue" excludes "blue" rather than adding everything else.
+                Some(Query::Not(query)) if occur != Occur::Excluded => {
+                    clauses.push((Occur::Excluded, *query));
+                }
+                Some(query) => clauses.push((occur, query)),
+                None => {}
+            }
+        }
+
+        if in_group && !found_clause {
+            return Err(format!(
+                "Empty parentheses at character {}.",
+                self.lexemes[self.next - 1].0
+            ));
+        }
+
+        Ok(match clauses.len() {
+            0 => None,
+            1 if clauses[0].0 != Occur::Excluded => clauses.pop().map(|(_, query)| query),
+            _ => Some(Query::Clauses(clauses)),
+        })
+    }
+
+    fn or(&mut self, after: Option<(usize, Lexeme)>) -> Result<Option<Query>, String> {
+        let mut parts: Vec<Query> = self.and(after)?.into_iter().collect();
+
+        while self.peek() == Some(&Lexeme::Or) {
+            let operator = self.advance();
+            parts.extend(self.and(Some(operator))?);
+        }
+
+        Ok(combine(parts, Query::Or))
+    }
+
+    fn and(&mut self, after: Option<(usize, Lexeme)>) -> Result<Option<Query>, String> {
+        let mut parts: Vec<Query> = self.unary(after)?.into_iter().collect();
+
+        while self.peek() == Some(&Lexeme::And) {
+            let operator = self.advance();
+            parts.extend(self.unary(Some(operator))?);
+        }
+
+        Ok(combine(parts, Query::And))
+    }
+
+    /// Parse a term, group or negation, following `after` if it is the operator before it.
+    fn unary(&mut self, after: Option<(usize, Lexeme)>) -> Result<Option<Query>, String> {
+        let expected_term = || match &after {
+            Some((at, operator)) => format!(
+                "Expected a term after '{}' at character {at}.",
+                operator.text()
+            ),
+            None => String::from("Expected a term."),
+        };
+
+        let Some(lexeme) = self.peek() else {
+            return Err(expected_term());
+        };
+
+        match lexeme {
+            Lexeme::Not | Lexeme::Minus => {
+                let operator = self.advance();
+                Ok(self
+                    .unary(Some(operator))?
+                    .map(|query| Query::Not(Box::new(query))))
+            }
+            Lexeme::Plus => {
+                let operator = self.advance();
+                self.unary(Some(operator))
+            }
+            &Lexeme::Field(field) => {
+                let operator = self.advance();
+                Ok(self
+                    .unary(Some(operator))?
+                    .map(|query| Query::Field(field, Box::new(query))))
+            }
+            Lexeme::Word(text) | Lexeme::Phrase(text) => {
+                let (at, _) = self.lexemes[self.next];
+                let query = normalise(text, self.analyzer)
+                    .map_err(|err| format!("{err} at character {at}."))?;
+                self.advance();
+                Ok(query)
+            }
+            Lexeme::Filter(filter) => {
+                let query = Query::Filter(filter.clone());
+                self.advance();
+                Ok(Some(query))
+            }
+            Lexeme::Open => {
+                self.advance();
+                let query = self.clauses(true)?;
+                self.advance();
+                Ok(query)
+            }
+            Lexeme::And | Lexeme::Or | Lexeme::Close | Lexeme::Sort(_) => Err(expected_term()),
+        }
+    }
+}
+
+/// Join the parts of an operator, leaving out the operator if there is only one part.
+fn combine(mut parts: Vec<Query>, operator: fn(Vec<Query>) -> Query) -> Option<Query> {
+    match parts.len() {
+        0 => None,
+        1 => parts.pop(),
+        _ => Some(operator(parts)),
+    }
+}
+
+/// Normalise a word or phrase from a query with the analyzer of the documents. Text which becomes
+/// several words is a phrase, and text which becomes none (e.g. a stop word) is dropped. Words
+/// analysed to the same position (e.g. synonyms) match the same documents, so only the first is
+/// kept.
+///
+/// Words with wildcards are only lowercased and stripped of punctuation, other than that which
+/// technical words keep (as in `iso-8601`, `v1.5`, `c++`, `c#` and `snake_case`), so they match
+/// the words of the vocabulary as they were analysed (e.g. stems). Words with a number of edits
+/// (`~2`, or `~` for 2) are stripped the same way, then analysed.
+fn normalise(text: &str, analyzer: &Analyzer) -> Result<Option<Query>, String> {
+    let simplify = |text: &str| -> String {
+        text.nfc()
+            .collect::<String>()
+            .to_lowercase()
+            .chars()
+            .filter(|&c| c.is_alphanumeric() || "-.+#_*?".contains(c))
+            .collect()
+    };
+
+    if let Some((word, edits)) = text.split_once('~') {
+        let edits: usize = match edits {
+            "" => 2,
+            edits => edits
+                .parse()
+                .map_err(|_| format!("Invalid number of edits in '{text}'"))?,
+        };
+        let word = simplify(word).replace(['*', '?'], "");
+        let word = analyzer
+            .analyse(&word)
+            .into_iter()
+            .next()
+            .map_or(word, |token| token.text);
+        return Ok((!word.is_empty()).then_some(Query::Fuzzy(word, edits)));
+    }
+
+    if text.contains(['*', '?']) {
+        return Ok(Some(Query::Wildcard(simplify(text))));
+    }
+
+    let mut tokens: Vec<Token> = analyzer.analyse(text);
+    tokens.dedup_by_key(|token| token.position);
+
+    Ok(match tokens.len() {
+        0 => None,
+        1 => tokens.pop().map(|token| Query::Term(token.text)),
+        _ => Some(Query::Phrase(tokens)),
+    })
+}
+
+/// Parse a query into its syntax tree, or `None` if normalisation leaves nothing of it. Words
+/// are analysed with the analyzer given, which should be the one the model was built with.
+pub fn parse(line: &str, analyzer: &Analyzer) -> Result<Option<Query>, String> {
+    parse_sorted(line, analyzer).map(|(query, _)| query)
+}
+
+/// Parse a query as `parse` does, along with the key it asks for results to be sorted by.
+pub fn parse_sorted(
+    line: &str,
+    analyzer: &Analyzer,
+) -> Result<(Option<Query>, Option<SortKey>), String> {
+    let mut parser = Parser {
+        lexemes: lex(line)?,
+        next: 0,
+        analyzer,
+        sort: None,
+    };
+
+    let query = parser.clauses(false)?;
+    Ok((query, parser.sort))
+}
+
+impl Query {
+    /// The documents matching the query.
+    pub fn matching_documents(&self, model: &Model, options: &QueryOptions) -> BTreeSet<usize> {
+        self.matching(model, options, None)
+    }
+
+    /// The documents matching the query, within a field if it is limited to one.
+    fn matching(
+        &self,
+        model: &Model,
+        options: &QueryOptions,
+        field: Option<Field>,
+    ) -> BTreeSet<usize> {
+        match self {
+            Query::Term(_) | Query::Wildcard(_) | Query::Fuzzy(..) | Query::Phrase(_) => self
+                .query_term(model, options, field)
+                .map_or(BTreeSet::new(), |term| {
+                    term.matches.iter().map(|posting| posting.doc).collect()
+                }),
+            Query::And(parts) => parts
+                .iter()
+                .map(|part| part.matching(model, options, field))
+                .reduce(|all, docs| &all & &docs)
+                .unwrap_or_default(),
+            Query::Or(parts) => parts
+                .iter()
+                .flat_map(|part| part.matching(model, options, field))
+                .collect(),
+            Query::Not(part) => &all_documents(model) - &part.matching(model, options, field),
+            Query::Clauses(clauses) => {
+                let matching = |occur: Occur| {
+                    clauses
+                        .iter()
+                        .filter(move |(o, _)| *o == occur)
+                        .map(|(_, part)| part.matching(model, options, field))
+                };
+
+                let mut docs: BTreeSet<usize> = matching(Occur::Required)
+                    .reduce(|all, docs| &all & &docs)
+                    .unwrap_or_else(|| {
+                        if clauses.iter().any(|(occur, _)| *occur == Occur::Optional) {
+                            matching(Occur::Optional).flatten().collect()
+                        } else {
+                            all_documents(model)
+                        }
+                    });
+
+                for excluded in matching(Occur::Excluded) {
+                    docs = &docs - &excluded;
+                }
+
+                docs
+            }
+            Query::Field(field, part) => part.matching(model, options, Some(*field)),
+            Query::Filter(filter) => (0..model.documents.len())
+                .filter(|&doc| filter.matches(&model.documents[doc].metadata))
+                .collect(),
+        }
+    }
+
+    /// The query term for a word or phrase, within a field if it is limited to one, or `None` if
+    /// a word is not in the corpus.
+    ///
+    /// Unless turned off in the options, a word which matches no documents is replaced by the
+    /// words within `automatic_edits` of it.
+    fn query_term(
+        &self,
+        model: &Model,
+        options: &QueryOptions,
+        field: Option<Field>,
+    ) -> Option<QueryTerm> {
+        match (self, field) {
+            (Query::Term(text), field) => {
+                let term: Option<QueryTerm> = match field {
+                    None => QueryTerm::from_word(model, text),
+                    Some(field) => model.vocabulary.id(&field.term(text)).map(|id| {
+                        let mut term = QueryTerm::from_term(model, id);
+                        term.text = format!("{}:{text}", field.name());
+                        term
+                    }),
+                };
+
+                match term {
+                    Some(term) if !term.matches.is_empty() => Some(term),
+                    term if options.fuzzy_fallback && automatic_edits(text) > 0 => {
+                        Query::Fuzzy(text.clone(), automatic_edits(text))
+                            .query_term(model, options, field)
+                            .filter(|similar| !similar.matches.is_empty())
+                            .or(term)
+                    }
+                    term => term,
+                }
+            }
+            (Query::Phrase(tokens), field) => {
+                let in_field = field.unwrap_or(Field::Body);
+                let field_tokens: Vec<Token> = tokens
+                    .iter()
+                    .map(|token| Token {
+                        text: in_field.term(&token.text),
+                        ..token.clone()
+                    })
+                    .collect();
+
+                let mut term = QueryTerm::from_phrase(model, &field_tokens);
+                if let Some(field) = field {
+                    let words: Vec<&str> = tokens.iter().map(|token| token.text.as_str()).collect();
+                    term.text = format!("{}:\"{}\"", field.name(), words.join(" "));
+                }
+                Some(term)
+            }
+            // The matching words are scored together as one, so that a pattern matching many
+            // rare words does not outweigh the rest of the query.
+            (Query::Wildcard(pattern), field) => {
+                let words: Vec<(String, f64)> =
+                    expand_wildcard(model, pattern, field, options.max_expansions)
+                        .into_iter()
+                        .map(|word| (word, 1.0))
+                        .collect();
+                let text = match field {
+                    Some(field) => format!("{}:{pattern}", field.name()),
+                    None => pattern.clone(),
+                };
+                Some(expanded_term(model, text, &words, field))
+            }
+            // Likewise for similar words, with the closer words counting for more.
+            (Query::Fuzzy(word, edits), field) => {
+                let words: Vec<(String, f64)> =
+                    expand_fuzzy(model, word, *edits, field, options.max_expansions)
+                        .into_iter()
+                        .map(|(word, distance)| (word, 1.0 / (1.0 + distance as f64)))
+                        .collect();
+                let text = match field {
+                    Some(field) => format!("{}:{word}~{edits}", field.name()),
+                    None => format!("{word}~{edits}"),
+                };
+                Some(expanded_term(model, text, &words, field))
+            }
+            _ => None,
+        }
+    }
+
+    /// The words and phrases a document is scored on, which are those not negated, with the
+    /// field each is limited to.
+    fn scored_parts<'a>(
+        &'a self,
+        field: Option<Field>,
+        parts: &mut Vec<(&'a Query, Option<Field>)>,
+    ) {
+        match self {
+            Query::Term(_) | Query::Wildcard(_) | Query::Fuzzy(..) | Query::Phrase(_) => {
+                parts.push((self, field));
+            }
+            Query::And(children) | Query::Or(children) => {
+                for child in children {
+                    child.scored_parts(field, parts);
+                }
+            }
+            Query::Not(_) | Query::Filter(_) => {}
+            Query::Clauses(clauses) => {
+                for (occur, child) in clauses {
+                    if *occur != Occur::Excluded {
+                        child.scored_parts(field, parts);
+                    }
+                }
+            }
+            Query::Field(field, child) => child.scored_parts(Some(*field), parts),
+        }
+    }
+
+    /// Whether any part of the query is a filter on the front matter.
+    fn has_filter(&self) -> bool {
+        match self {
+            Query::Filter(_) => true,
+            Query::And(children) | Query::Or(children) => children.iter().any(Query::has_filter),
+            Query::Not(child) | Query::Field(_, child) => child.has_filter(),
+            Query::Clauses(clauses) => clauses.iter().any(|(_, child)| child.has_filter()),
+            Query::Term(_) | Query::Wildcard(_) | Query::Fuzzy(..) | Query::Phrase(_) => false,
+        }
+    }
+
+    /// The words of the corpus which the scored parts of the query look for, to highlight in
+    /// results: its words and the words of its phrases, without their fields, and the words its
+    /// wildcards and similar words stand for.
+    pub fn highlight_words(&self, model: &Model, options: &QueryOptions) -> BTreeSet<String> {
+        let mut parts: Vec<(&Query, Option<Field>)> = Vec::new();
+        self.scored_parts(None, &mut parts);
+
+        let similar = |word: &str, edits: usize, field: Option<Field>| {
+            expand_fuzzy(model, word, edits, field, options.max_expansions)
+                .into_iter()
+                .map(|(word, _)| word)
+        };
+
+        let mut words: BTreeSet<String> = BTreeSet::new();
+        for (part, field) in parts {
+            match part {
+                Query::Term(word) => {
+                    words.insert(word.clone());
+                    let found = QueryTerm::from_word(model, word)
+                        .is_some_and(|term| !term.matches.is_empty());
+                    if options.fuzzy_fallback && !found {
+                        words.extend(similar(word, automatic_edits(word), field));
+                    }
+                }
+                Query::Phrase(tokens) => {
+                    words.extend(tokens.iter().map(|token| token.text.clone()))
+                }
+                Query::Wildcard(pattern) => {
+                    words.extend(expand_wildcard(
+                        model,
+                        pattern,
+                        field,
+                        options.max_expansions,
+                    ));
+                }
+                Query::Fuzzy(word, edits) => words.extend(similar(word, *edits, field)),
+                _ => {}
+            }
+        }
+        words
+    }
+
+    /// The query terms a document is scored on. Repeated words are weighted by how often they
+    /// occur, and words which are not in the corpus are left out.
+    pub fn query_terms(&self, model: &Model, options: &QueryOptions) -> Vec<QueryTerm> {
+        let mut parts: Vec<(&Query, Option<Field>)> = Vec::new();
+        self.scored_parts(None, &mut parts);
+
+        let mut terms: Vec<QueryTerm> = Vec::new();
+        for (part, field) in parts {
+            let Some(term) = part.query_term(model, options, field) else {
+                continue;
+            };
+
+            match terms.iter_mut().find(|other| other.text == term.text) {
+                Some(other) => other.weight += 1.0,
+                None => terms.push(term),
+            }
+        }
+
+        terms
+    }
+}
+
+fn all_documents(model: &Model) -> BTreeSet<usize> {
+    (0..model.documents.len()).collect()
+}
+
+/// The closest word of the corpus to a word which is not in it (within a field, or else in any
+/// field), preferring words which occur in more documents.
+fn correct_word(model: &Model, word: &str, field: Option<Field>) -> Option<String> {
+    if QueryTerm::from_word(model, word).is_some_and(|term| !term.matches.is_empty()) {
+        return None;
+    }
+
+    let max_edits = automatic_edits(word).max(1);
+    expand_fuzzy(model, word, max_edits, field, 1)
+        .into_iter()
+        .next()
+        .map(|(word, _)| word)
+}
+
+/// Suggest a corrected query, with each word which is not in the corpus replaced by the closest
+/// word which is, or `None` if there is nothing to correct.
+///
+/// Operators, wildcards and the rest of the query are kept as they were written.
+pub fn suggest_correction(line: &str, model: &Model) -> Option<String> {
+    let lexemes = lex(line).ok()?;
+    // The (0-based) character range of each correction and its replacement.
+    let mut corrections: Vec<(usize, usize, String)> = Vec::new();
+
+    for (i, (at, lexeme)) in lexemes.iter().enumerate() {
+        let field = match i.checked_sub(1).map(|previous| &lexemes[previous].1) {
+            Some(&Lexeme::Field(field)) => Some(field),
+            _ => None,
+        };
+        // The words of a phrase start after its opening quote.
+        let (start, text) = match lexeme {
+            Lexeme::Word(text) => (at - 1, text),
+            Lexeme::Phrase(text) => (*at, text),
+            _ => continue,
+        };
+        if text.contains(['*', '?', '~']) {
+            continue;
+        }
+
+        let mut offset = 0;
+        for word in text.split(' ') {
+            let length = word.chars().count();
+            if let Ok(Some(Query::Term(normalised))) = normalise(word, &model.analyzer) {
+                if let Some(correction) = correct_word(model, &normalised, field) {
+                    // Only the word is replaced, not the punctuation written next to it.
+                    let (from, to) = model
+                        .analyzer
+                        .analyse_source(word)
+                        .into_iter()
+                        .find(|token| token.text == normalised)
+                        .map_or((0, length), |token| {
+                            (
+                                word[..token.span.start].chars().count(),
+                                word[..token.span.end].chars().count(),
+                            )
+                        });
+                    corrections.push((start + offset + from, start + offset + to, correction));
+                }
+            }
+            offset += length + 1;
+        }
+    }
+
+    if corrections.is_empty() {
+        return None;
+    }
+
+    let chars: Vec<char> = line.chars().collect();
+    let mut corrected = String::new();
+    let mut next = 0;
+    for (start, end, correction) in corrections {
+        corrected.extend(&chars[next..start]);
+        corrected.push_str(&correction);
+        next = end;
+    }
+    corrected.extend(&chars[next..]);
+
+    Some(corrected.trim().to_string())
+}
+
+/// Settings for turning queries into query terms.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryOptions {
+    /// Most words a wildcard pattern or misspelt word is expanded to.
+    pub max_expansions: usize,
+    /// Whether to look for similar words when a word matches no documents.
+    pub fuzzy_fallback: bool,
+}
+
+impl Default for QueryOptions {
+    fn default() -> Self {
+        Self {
+            max_expansions: 50,
+            fuzzy_fallback: true,
+        }
+    }
+}
+
+/// A query ready to be ranked: the documents it matches, the terms they are scored on and the
+/// key, if any, to order them by instead of their scores.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreparedQuery {
+    pub terms: Vec<QueryTerm>,
+    pub documents: BTreeSet<usize>,
+    pub sort: Option<SortKey>,
+    /// The words to highlight in results (see `Query::highlight_words`).
+    pub words: BTreeSet<String>,
+}
+
+impl PreparedQuery {
+    /// A query matching the documents which contain any of its terms.
+    pub fn from_terms(terms: Vec<QueryTerm>) -> Self {
+        let documents: BTreeSet<usize> = terms
+            .iter()
+            .flat_map(|term| term.matches.iter().map(|posting| posting.doc))
+            .collect();
+
+        let words: BTreeSet<String> = terms.iter().map(|term| term.text.clone()).collect();
+
+        Self {
+            terms,
+            documents,
+            sort: None,
+            words,
+        }
+    }
+}
+
+/// Parse a line of user input and find the documents it matches.
+///
+/// Words side by side are alternatives, of which documents must match at least one, but words
+/// marked `+` are required and words marked `-` excluded. Quoted phrases and filters are required
+/// unless combined with an operator. `AND`, `OR` and `NOT` (in capitals) combine words, phrases and
+/// groups in parentheses, `NOT` binding most tightly and `OR` least. A word, phrase or group can
+/// be limited to a field of the documents, e.g. `title:ownership` or `tag:(rust OR go)`. Words
+/// can contain the wildcards `*` and `?`, or end in `~` and a number of edits to match similar
+/// words.
+///
+/// Any other key and a colon filters on the front matter of the documents, e.g. `status:draft`
+/// or `date:>=2025-01-01`, and `sort:date` (or `sort:-date`) orders the results by a key. A query
+/// of only filters matches every document which meets them, and a sort key on its own orders
+/// every document.
+pub fn parse_query(
+    line: &str,
+    model: &Model,
+    options: &QueryOptions,
+) -> Result<PreparedQuery, String> {
+    let (query, sort) = parse_sorted(line, &model.analyzer)?;
+    let Some(query) = query else {
+        if sort.is_some() {
+            return Ok(PreparedQuery {
+                terms: Vec::new(),
+                documents: all_documents(model),
+                sort,
+                words: BTreeSet::new(),
+            });
+        }
+        return Err(String::from(
+            "no input (stop words were stripped from the query)",
+        ));
+    };
+
+    let mut parts: Vec<(&Query, Option<Field>)> = Vec::new();
+    query.scored_parts(None, &mut parts);
+    if parts.is_empty() && !query.has_filter() {
+        return Err(String::from(
+            "the query only excludes words; add something to search for",
+        ));
+    }
+
+    Ok(PreparedQuery {
+        terms: query.query_terms(model, options),
+        documents: query.matching_documents(model, options),
+        sort,
+        words: query.highlight_words(model, options),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::DocumentInfo;
+    use crate::text_process::{self, AsciiFolding, Language, Stem, Synonyms};
+
+    fn test_model() -> Model {
+        let texts = [
+            "the rust borrow checker",
+            "the borrow of rust checker",
+            "rust and the borrow checker",
+        ];
+        let corpus: Vec<Vec<Token>> = texts
+            .iter()
+            .map(|text| text_process::tokenise_positions(text).unwrap())
+            .collect();
+        let documents: Vec<DocumentInfo> = corpus
+            .iter()
+            .map(|tokens| DocumentInfo {
+                length: tokens.len() as u32,
+                ..Default::default()
+            })
+            .collect();
+        Model::new(documents, &corpus)
+    }
+
+    #[test]
+    fn test_lex() {
+        let result = lex(r#"+sky -"rust borrow" (e-mail OR NOT sun) - c"#).unwrap();
+
+        assert_eq!(
+            result,
+            vec![
+                (1, Lexeme::Plus),
+                (2, Lexeme::Word("sky".to_string())),
+                (6, Lexeme::Minus),
+                (7, Lexeme::Phrase("rust borrow".to_string())),
+                (21, Lexeme::Open),
+                (22, Lexeme::Word("e-mail".to_string())),
+                (29, Lexeme::Or),
+                (32, Lexeme::Not),
+                (36, Lexeme::Word("sun".to_string())),
+                (39, Lexeme::Close),
+                (41, Lexeme::Word("-".to_string())),
+                (43, Lexeme::Word("c".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let term = |text: &str| Query::Term(text.to_string());
+
+        let result = parse("rust OR borrow AND NOT checker", &Analyzer::default()).unwrap();
+        assert_eq!(
+            result,
+            Some(Query::Or(vec![
+                term("rust"),
+                Query::And(vec![term("borrow"), Query::Not(Box::new(term("checker")))])
+            ]))
+        );
+
+        // Stop words are dropped, along with operators left with nothing to apply to.
+        let result = parse("+rust the -(of AND it) (sky OR sun)", &Analyzer::default()).unwrap();
+        assert_eq!(
+            result,
+            Some(Query::Clauses(vec![
+                (Occur::Required, term("rust")),
+                (Occur::Optional, Query::Or(vec![term("sky"), term("sun")])),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_parse_fields() {
+        let result = parse(
+            r#"tag:(rust OR go) -title:"borrow checker" path:projects/"#,
+            &Analyzer::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            result,
+            Some(Query::Clauses(vec![
+                (
+                    Occur::Optional,
+                    Query::Field(
+                        Field::Tag,
+                        Box::new(Query::Or(vec![
+                            Query::Term("rust".to_string()),
+                            Query::Term("go".to_string())
+                        ]))
+                    )
+                ),
+                (
+                    Occur::Excluded,
+                    Query::Field(
+                        Field::Title,
+                        Box::new(Query::Phrase(vec![
+                            Token {
+                                text: "borrow".to_string(),
+                                position: 0,
+                                span: 0..6
+                            },
+                            Token {
+                                text: "checker".to_string(),
+                                position: 1,
+                                span: 7..14
+                            }
+                        ]))
+                    )
+                ),
+                (
+                    Occur::Optional,
+                    Query::Field(Field::Path, Box::new(Query::Term("projects".to_string())))
+                ),
+            ]))
+        );
+
+        // Other keys filter on the front matter instead.
+        let result = parse("subtitle:sky", &Analyzer::default()).unwrap();
+        assert_eq!(result, Some(Query::Filter(Filter::new("subtitle", "sky"))));
+    }
+
+    #[test]
+    fn test_lex_metadata() {
+        let result =
+            lex(r#"date:>=2025-01-01 -author:"Ada Lovelace" sort:-date https://a.b"#).unwrap();
+
+        assert_eq!(
+            result,
+            vec![
+                (1, Lexeme::Filter(Filter::new("date", ">=2025-01-01"))),
+                (19, Lexeme::Minus),
+                (20, Lexeme::Filter(Filter::new("author", "Ada Lovelace"))),
+                (42, Lexeme::Sort(SortKey::new("-date"))),
+                (53, Lexeme::Word("https://a.b".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let cases = [
+            ("title:", "Expected a term after 'title:' at character 1."),
+            ("sky AND", "Expected a term after 'AND' at character 5."),
+            ("OR sky", "Expected a term before 'OR' at character 1."),
+            ("(sky OR sun", "Missing closing parenthesis."),
+            ("sky) sun", "Unmatched ')' at character 4."),
+            ("sky ()", "Empty parentheses at character 5."),
+            ("sky NOT", "Expected a term after 'NOT' at character 5."),
+            (
+                r#"sky "blue sun"#,
+                "Missing closing quote for the phrase at character 5.",
+            ),
+        ];
+
+        for (line, error) in cases {
+            assert_eq!(
+                parse(line, &Analyzer::default()),
+                Err(error.to_string()),
+                "{line}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_phrase_matches() {
+        let model = test_model();
+        let rust = model.vocabulary.id("rust").unwrap();
+        let borrow = model.vocabulary.id("borrow").unwrap();
+        let checker = model.vocabulary.id("checker").unwrap();
+
+        let result = phrase_matches(&model, &[(rust, 0), (borrow, 1), (checker, 2)]);
+        assert_eq!(
+            result,
+            vec![Posting {
+                doc: 0,
+                tf: 1.0,
+                positions: vec![1]
+            }]
+        );
+
+        // Stop words leave gaps which must be the same as in the query.
+        let result = phrase_matches(&model, &[(borrow, 0), (rust, 2)]);
+        assert_eq!(
+            result,
+            vec![Posting {
+                doc: 1,
+                tf: 1.0,
+                positions: vec![1]
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_query_phrase() {
+        let model = test_model();
+
+        let result =
+            parse_query(r#""rust borrow checker""#, &model, &QueryOptions::default()).unwrap();
+
+        assert_eq!(result.terms.len(), 1);
+        assert_eq!(result.terms[0].text, "\"rust borrow checker\"");
+        assert_eq!(result.terms[0].stats.doc_freq, 1);
+        assert_eq!(result.documents, BTreeSet::from([0]));
+
+        // "rust and the borrow" leaves the same gap as "rust of the borrow".
+        let result = parse_query(
+            r#""rust of the borrow checker""#,
+            &model,
+            &QueryOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(result.documents, BTreeSet::from([2]));
+
+        // Phrases are required, unlike loose words.
+        let result = parse_query(
+            r#"checker "rust of the borrow""#,
+            &model,
+            &QueryOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(result.documents, BTreeSet::from([2]));
+    }
+
+    #[test]
+    fn test_parse_query_words() {
+        let model = test_model();
+
+        let result = parse_query(
+            "rust unknown rust checker",
+            &model,
+            &QueryOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(result.terms.len(), 2);
+        assert_eq!(result.terms[0].text, "rust");
+        assert_eq!(result.terms[0].weight, 2.0);
+        assert_eq!(result.terms[0].matches.len(), 3);
+        assert_eq!(result.terms[1].text, "checker");
+        assert_eq!(result.documents, BTreeSet::from([0, 1, 2]));
+    }
+
+    #[test]
+    fn test_parse_query_analyzer() {
+        let analyzer = Analyzer::default()
+            .with_token_filter(Box::new(Synonyms::new(vec![vec![
+                "checker".to_string(),
+                "verifier".to_string(),
+            ]])))
+            .with_token_filter(Box::new(Stem {
+                language: Language::English,
+            }));
+        let texts = ["borrowing checker", "the checker borrowed"];
+        let corpus: Vec<Vec<Token>> = texts.iter().map(|text| analyzer.analyse(text)).collect();
+        let documents: Vec<DocumentInfo> = vec![DocumentInfo::default(); 2];
+        let mut model = Model::new(documents, &corpus);
+        model.analyzer = analyzer;
+        let options = QueryOptions::default();
+
+        let result = parse_query("borrows", &model, &options).unwrap();
+        assert_eq!(result.terms[0].text, "borrow");
+        assert_eq!(result.documents, BTreeSet::from([0, 1]));
+
+        let result = parse_query(r#""borrowed checkers""#, &model, &options).unwrap();
+        assert_eq!(result.documents, BTreeSet::from([0]));
+
+        // Wildcards match the stems in the vocabulary, and misspellings are stemmed first.
+        let result = parse_query("borrowi*", &model, &options).unwrap();
+        assert!(result.documents.is_empty());
+        let result = parse_query("checkerz~1", &model, &options).unwrap();
+        assert_eq!(result.documents, BTreeSet::from([0, 1]));
+
+        // Synonyms match each other, in phrases too.
+        let result = parse_query(r#""borrowing verifier""#, &model, &options).unwrap();
+        assert_eq!(result.terms[0].text, "\"borrow verifi\"");
+        assert_eq!(result.documents, BTreeSet::from([0]));
+    }
+
+    #[test]
+    fn test_parse_query_unicode() {
+        let analyzer = Analyzer::default().with_token_filter(Box::new(AsciiFolding));
+        let texts = ["un café crème", "Καλημέρα κόσμε", "coffee"];
+        let corpus: Vec<Vec<Token>> = texts.iter().map(|text| analyzer.analyse(text)).collect();
+        let documents: Vec<DocumentInfo> = vec![DocumentInfo::default(); 3];
+        let mut model = Model::new(documents, &corpus);
+        model.analyzer = analyzer;
+        let options = QueryOptions::default();
+
+        let result = parse_query("CAFÉ", &model, &options).unwrap();
+        assert_eq!(result.terms[0].text, "cafe");
+        assert_eq!(result.documents, BTreeSet::from([0]));
+
+        let result = parse_query("καλημερα", &model, &options).unwrap();
+        assert_eq!(result.documents, BTreeSet::from([1]));
+
+        let result = parse_query("κοσ*", &model, &options).unwrap();
+        assert_eq!(result.documents, BTreeSet::from([1]));
+    }
+
+    #[test]
+    fn test_parse_query_technical() {
+        let analyzer = Analyzer::default();
+        let texts = [
+            "Dates in ISO-8601, served over HTTP2",
+            "Upgrade regex to v1.5.5 for C++ error E0502",
+            "C is not C++ and http is not http2",
+            "An example entry",
+        ];
+        let corpus: Vec<Vec<Token>> = texts.iter().map(|text| analyzer.analyse(text)).collect();
+        let documents: Vec<DocumentInfo> = vec![DocumentInfo::default(); 4];
+        let model = Model::new(documents, &corpus);
+        let documents = |line: &str| {
+            parse_query(line, &model, &QueryOptions::default())
+                .unwrap()
+                .documents
+        };
+
+        assert_eq!(documents("iso-8601"), BTreeSet::from([0]));
+        assert_eq!(documents("8601"), BTreeSet::from([0]));
+        assert_eq!(documents(r#""iso 8601""#), BTreeSet::from([0]));
+        assert_eq!(documents("v1.5.5"), BTreeSet::from([1]));
+        assert_eq!(documents("e0502"), BTreeSet::from([1]));
+        assert_eq!(documents("+c++ +error"), BTreeSet::from([1]));
+        assert_eq!(documents("+http2 +served"), BTreeSet::from([0]));
+
+        // Wildcards and similar words keep their digits and punctuation.
+        assert_eq!(
+            parse("E05*", &analyzer).unwrap(),
+            Some(Query::Wildcard("e05*".to_string()))
+        );
+        assert_eq!(documents("e05*"), BTreeSet::from([1]));
+        assert_eq!(documents("v1.*"), BTreeSet::from([1]));
+        assert_eq!(
+            parse("http2~1", &analyzer).unwrap(),
+            Some(Query::Fuzzy("http2".to_string(), 1))
+        );
+        assert_eq!(documents("http2~1"), BTreeSet::from([0, 2]));
+        assert_eq!(documents("e0503~1"), BTreeSet::from([1]));
+    }
+
+    #[test]
+    fn test_parse_query_operators() {
+        let model = test_model();
+        let documents = |line: &str| {
+            parse_query(line, &model, &QueryOptions::default())
+                .unwrap()
+                .documents
+        };
+
+        assert_eq!(
+            documents(r#"borrow -"rust borrow""#),
+            BTreeSet::from([1, 2])
+        );
+        assert_eq!(
+            documents(r#"checker NOT "rust borrow""#),
+            BTreeSet::from([1, 2])
+        );
+        assert_eq!(
+            documents(r#""rust borrow" OR "borrow of rust""#),
+            BTreeSet::from([0, 1])
+        );
+        assert_eq!(
+            documents(r#"+rust +checker -("borrow checker" OR "rust borrow")"#),
+            BTreeSet::from([1])
+        );
+        assert_eq!(documents("unknown AND rust"), BTreeSet::new());
+
+        // Excluded words are not scored.
+        let result = parse_query("rust -borrow", &model, &QueryOptions::default()).unwrap();
+        assert_eq!(result.terms.len(), 1);
+        assert_eq!(result.terms[0].text, "rust");
+    }
+
+    #[test]
+    fn test_parse_query_fields() {
+        let field_tokens = |field: Field, text: &str| -> Vec<Token> {
+            text_process::tokenise_positions(text)
+                .unwrap()
+                .into_iter()
+                .map(|token| Token {
+                    text: field.term(&token.text),
+                    ..token
+                })
+                .collect()
+        };
+        let corpus: Vec<Vec<Token>> = vec![
+            [
+                field_tokens(Field::Body, "rust borrow checker"),
+                field_tokens(Field::Title, "ownership rules"),
+                field_tokens(Field::Tag, "rust"),
+            ]
+            .concat(),
+            [
+                field_tokens(Field::Body, "ownership of rust"),
+                field_tokens(Field::Path, "projects notes"),
+            ]
+            .concat(),
+        ];
+        let documents: Vec<DocumentInfo> = vec![
+            DocumentInfo {
+                length: 3,
+                ..Default::default()
+            },
+            DocumentInfo {
+                length: 2,
+                ..Default::default()
+            },
+        ];
+        let model = Model::new(documents, &corpus);
+        let documents = |line: &str| {
+            parse_query(line, &model, &QueryOptions::default())
+                .unwrap()
+                .documents
+        };
+
+        assert_eq!(documents("ownership"), BTreeSet::from([0, 1]));
+        assert_eq!(documents("title:ownership"), BTreeSet::from([0]));
+        assert_eq!(documents("body:ownership"), BTreeSet::from([1]));
+        assert_eq!(documents(r#"title:"ownership rules""#), BTreeSet::from([0]));
+        assert_eq!(documents(r#"title:"rules ownership""#), BTreeSet::new());
+        assert_eq!(documents("path:projects/"), BTreeSet::from([1]));
+        assert_eq!(documents("tag:(rust OR go)"), BTreeSet::from([0]));
+
+        // Occurrences of a word in each field are weighted by the field's boost.
+        let result = parse_query("ownership", &model, &QueryOptions::default()).unwrap();
+        let tfs: Vec<f64> = result.terms[0]
+            .matches
+            .iter()
+            .map(|posting| posting.tf)
+            .collect();
+        assert_eq!(tfs, vec![Field::Title.boost(), 1.0]);
+        assert_eq!(result.terms[0].stats.doc_freq, 2);
+
+        let result = parse_query("title:ownership", &model, &QueryOptions::default()).unwrap();
+        assert_eq!(result.terms[0].text, "title:ownership");
+        assert_eq!(result.terms[0].matches[0].tf, 1.0);
+    }
+
+    #[test]
+    fn test_parse_query_metadata() {
+        let texts = [
+            "---\nstatus: draft\ndate: 2025-03-01\ntags: [rust]\n---\nrust borrow checker",
+            "---\nstatus: published\ndate: 2024-11-20\n---\nrust ownership",
+            "no front matter about rust",
+        ];
+        let documents: Vec<DocumentInfo> = texts
+            .iter()
+            .map(|text| DocumentInfo {
+                length: 3,
+                metadata: crate::document::Document::parse(text),
+                ..Default::default()
+            })
+            .collect();
+        let corpus: Vec<Vec<Token>> = texts
+            .iter()
+            .map(|text| text_process::tokenise_positions(text).unwrap())
+            .collect();
+        let model = Model::new(documents, &corpus);
+        let prepare = |line: &str| parse_query(line, &model, &QueryOptions::default()).unwrap();
+
+        assert_eq!(prepare("rust status:draft").documents, BTreeSet::from([0]));
+        assert_eq!(
+            prepare("rust -status:draft").documents,
+            BTreeSet::from([1, 2])
+        );
+        assert_eq!(prepare("date:<2025-01-01").documents, BTreeSet::from([1]));
+
+        // Filters are not scored, so they leave the statistics of the terms alone.
+        let result = prepare("rust status:draft");
+        assert_eq!(result.terms.len(), 1);
+        assert_eq!(result.terms[0].text, "rust");
+        assert_eq!(result.terms[0].stats.doc_freq, 3);
+
+        let result = prepare("rust sort:-date");
+        assert_eq!(result.sort, Some(SortKey::new("-date")));
+        assert_eq!(result.documents, BTreeSet::from([0, 1, 2]));
+        let result = prepare("sort:date");
+        assert!(result.terms.is_empty());
+        assert_eq!(result.documents, BTreeSet::from([0, 1, 2]));
+    }
+
+    #[test]
+    fn test_parse_query_wildcards() {
+        let corpus: Vec<Vec<Token>> = ["borrow borrowed", "borrowing checker", "check chuck"]
+            .iter()
+            .map(|text| text_process::tokenise_positions(text).unwrap())
+            .collect();
+        let documents: Vec<DocumentInfo> = vec![
+            DocumentInfo {
+                length: 2,
+                ..Default::default()
+            };
+            3
+        ];
+        let model = Model::new(documents, &corpus);
+        let options = QueryOptions::default();
+        let documents = |line: &str| parse_query(line, &model, &options).unwrap().documents;
+
+        assert_eq!(
+            parse("Borrow*", &Analyzer::default()).unwrap(),
+            Some(Query::Wildcard("borrow*".to_string()))
+        );
+        assert_eq!(documents("borrow*"), BTreeSet::from([0, 1]));
+        assert_eq!(documents("ch?ck"), BTreeSet::from([2]));
+        assert_eq!(documents("*ing OR *er"), BTreeSet::from([1]));
+        assert_eq!(documents("path:borrow*"), BTreeSet::new());
+
+        // The matching words are scored as one term, rather than one term each.
+        let result = parse_query("borrow*", &model, &options).unwrap();
+        assert_eq!(result.terms.len(), 1);
+        assert_eq!(result.terms[0].stats.doc_freq, 2);
+        assert_eq!(result.terms[0].matches[0].tf, 2.0);
+        assert_eq!(result.terms[0].matches[0].positions, vec![0, 1]);
+
+        // Only the most common matches are kept.
+        let result = expand_wildcard(&model, "ch*", None, 2);
+        assert_eq!(result, vec!["check".to_string(), "checker".to_string()]);
+        let result = expand_wildcard(&model, "borrow*", None, 50);
+        assert_eq!(result.len(), 3);
+    }
+
+    #[test]
+    fn test_parse_query_fuzzy() {
+        let model = test_model();
+        let options = QueryOptions::default();
+        let exact = QueryOptions {
+            fuzzy_fallback: false,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            parse("Borow~1 chekcer~", &Analyzer::default()).unwrap(),
+            Some(Query::Clauses(vec![
+                (Occur::Optional, Query::Fuzzy("borow".to_string(), 1)),
+                (Occur::Optional, Query::Fuzzy("chekcer".to_string(), 2)),
+            ]))
+        );
+
+        let result = parse_query("borow~1", &model, &options).unwrap();
+        assert_eq!(result.terms[0].text, "borow~1");
+        assert_eq!(result.documents, BTreeSet::from([0, 1, 2]));
+        // One edit away, so each occurrence counts for half.
+        assert_eq!(result.terms[0].matches[0].tf, 0.5);
+
+        // A transposition is one edit.
+        let result = parse_query("rsut~1", &model, &options).unwrap();
+        assert_eq!(result.documents, BTreeSet::from([0, 1, 2]));
+
+        // Misspelt words fall back to similar words, unless turned off.
+        let result = parse_query("chekcer", &model, &options).unwrap();
+        assert_eq!(result.terms[0].text, "chekcer~2");
+        assert_eq!(result.documents, BTreeSet::from([0, 1, 2]));
+        // Results highlight the words found in place of the misspelt one.
+        assert_eq!(
+            result.words,
+            BTreeSet::from(["checker".to_string(), "chekcer".to_string()])
+        );
+        let result = parse_query("chekcer", &model, &exact).unwrap();
+        assert_eq!(result.documents, BTreeSet::new());
+
+        // Words matching exactly are left alone, and short words are not expanded.
+        let result = parse_query("rust xy", &model, &options).unwrap();
+        assert_eq!(result.terms.len(), 1);
+        assert_eq!(result.terms[0].text, "rust");
+
+        assert_eq!(
+            parse("borow~x", &Analyzer::default()),
+            Err(String::from(
+                "Invalid number of edits in 'borow~x' at character 1."
+            ))
+        );
+    }
+
+    #[test]
+    fn test_suggest_correction() {
+        let model = test_model();
+
+        // There are no titles to correct "chekcer" to.
+        let result = suggest_correction(
+            r#"Rsut AND "borow  checker" NOT title:chekcer borrow*  +checkr~1"#,
+            &model,
+        );
+        assert_eq!(
+            result,
+            Some(String::from(
+                r#"rust AND "borrow  checker" NOT title:chekcer borrow*  +checkr~1"#
+            ))
+        );
+
+        // Punctuation next to a word is kept.
+        assert_eq!(
+            suggest_correction("rsut, \"borow.\" (chekcer)", &model),
+            Some(String::from("rust, \"borrow.\" (checker)"))
+        );
+
+        // Nothing to correct: known words, stop words, and words which are not like any other.
+        assert_eq!(suggest_correction("rust OR the checker", &model), None);
+        assert_eq!(suggest_correction("zzzzzzzz", &model), None);
+    }
+
+    #[test]
+    fn test_parse_query_error() {
+        let model = test_model();
+
+        let result = parse_query("the \"and\" is", &model, &QueryOptions::default());
+        assert_eq!(
+            Err(String::from(
+                "no input (stop words were stripped from the query)"
+            )),
+            result
+        );
+
+        let result = parse_query("-rust NOT borrow", &model, &QueryOptions::default());
+        assert_eq!(
+            Err(String::from(
+                "the query only excludes words; add something to search for"
+            )),
+            result
+        );
+    }
+}
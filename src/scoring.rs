@@ -0,0 +1,719 @@
+use super::calculations;
+use super::model::Model;
+use super::query::{self, QueryTerm};
+
+/// Collection statistics of a query term (or phrase).
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TermStats {
+    /// Number of documents the term occurs in.
+    pub doc_freq: u32,
+    /// Total number of occurrences of the term in the collection.
+    pub collection_freq: u64,
+}
+
+/// A ranking function.
+///
+/// A document's score is the sum over the query terms of the query term weight times
+/// `term_score`, passed through `normalise`. `term_score` is called for every query term of a
+/// candidate document, including those which do not occur in it (`tf == 0`).
+pub trait Scorer {
+    /// Contribution of one occurrence of a query term to the score of a document.
+    fn term_score(&self, model: &Model, stats: &TermStats, tf: f64, doc: usize) -> f64;
+
+    /// Whether the occurrences of a word count for the boost of their field (see `Field::boost`)
+    /// rather than once. Language models count them once, as they divide frequencies by lengths
+    /// in tokens.
+    fn boosts_fields(&self) -> bool {
+        true
+    }
+
+    /// Adjust the summed term scores of a document; by default they are left as they are.
+    fn normalise(&self, _model: &Model, _query: &[QueryTerm], _doc: usize, score: f64) -> f64 {
+        score
+    }
+
+    /// The named values `term_score` is worked out from, e.g. the IDF of the term, to explain a
+    /// score. They only describe it; the score itself always comes from `term_score`.
+    fn explain_term(&self, _model: &Model, _stats: &TermStats, _tf: f64, _doc: usize) -> Factors {
+        Vec::new()
+    }
+
+    /// The named values `normalise` is worked out from, to explain a score.
+    fn explain_normalise(
+        &self,
+        _model: &Model,
+        _query: &[QueryTerm],
+        _doc: usize,
+        _score: f64,
+    ) -> Factors {
+        Vec::new()
+    }
+}
+
+/// Named values which go into a score, e.g. `("idf", 1.2)`.
+pub type Factors = Vec<(&'static str, f64)>;
+
+/// Raw term counts weighted by IDF, compared by cosine similarity.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CosineTfIdf;
+
+impl Scorer for CosineTfIdf {
+    fn term_score(&self, model: &Model, stats: &TermStats, tf: f64, _doc: usize) -> f64 {
+        // The product of the query and document weights, (q_tf * idf) * (tf * idf).
+        let idf: f64 = calculations::idf(model.documents.len(), stats.doc_freq);
+        tf * idf * idf
+    }
+
+    fn normalise(&self, model: &Model, query: &[QueryTerm], doc: usize, score: f64) -> f64 {
+        calculations::cosine_similarity(score, query_norm(model, query), model.norms[doc])
+    }
+
+    fn explain_term(&self, model: &Model, stats: &TermStats, _tf: f64, _doc: usize) -> Factors {
+        vec![(
+            "idf",
+            calculations::idf(model.documents.len(), stats.doc_freq),
+        )]
+    }
+
+    fn explain_normalise(
+        &self,
+        model: &Model,
+        query: &[QueryTerm],
+        doc: usize,
+        _score: f64,
+    ) -> Factors {
+        vec![
+            ("query norm", query_norm(model, query)),
+            ("document norm", model.norms[doc]),
+        ]
+    }
+}
+
+/// The magnitude of the TF-IDF vector of a query.
+fn query_norm(model: &Model, query: &[QueryTerm]) -> f64 {
+    calculations::vector_magnitude(
+        &query
+            .iter()
+            .map(|term| term.weight * calculations::idf(model.documents.len(), term.stats.doc_freq))
+            .collect::<Vec<f64>>(),
+    )
+}
+
+/// Okapi BM25.
+///
+/// `k1` controls how quickly repeated occurrences of a term stop adding to the score, and `b` how
+/// strongly scores are normalised by document length.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bm25 {
+    pub k1: f64,
+    pub b: f64,
+}
+
+impl Default for Bm25 {
+    fn default() -> Self {
+        Self { k1: 1.2, b: 0.75 }
+    }
+}
+
+impl Scorer for Bm25 {
+    fn term_score(&self, model: &Model, stats: &TermStats, tf: f64, doc: usize) -> f64 {
+        if tf == 0.0 {
+            return 0.0;
+        }
+
+        bm25_idf(model, stats) * bm25_tf(self.k1, self.b, model, tf, doc)
+    }
+
+    fn explain_term(&self, model: &Model, stats: &TermStats, tf: f64, doc: usize) -> Factors {
+        explain_bm25(self.k1, self.b, model, stats, tf, doc)
+    }
+}
+
+/// BM25+, which adds `delta` to the contribution of every matching term so that very long
+/// documents are not scored below documents which do not contain the term at all.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bm25Plus {
+    pub k1: f64,
+    pub b: f64,
+    pub delta: f64,
+}
+
+impl Default for Bm25Plus {
+    fn default() -> Self {
+        Self {
+            k1: 1.2,
+            b: 0.75,
+            delta: 1.0,
+        }
+    }
+}
+
+impl Scorer for Bm25Plus {
+    fn term_score(&self, model: &Model, stats: &TermStats, tf: f64, doc: usize) -> f64 {
+        if tf == 0.0 {
+            return 0.0;
+        }
+
+        bm25_idf(model, stats) * (bm25_tf(self.k1, self.b, model, tf, doc) + self.delta)
+    }
+
+    fn explain_term(&self, model: &Model, stats: &TermStats, tf: f64, doc: usize) -> Factors {
+        let mut factors = explain_bm25(self.k1, self.b, model, stats, tf, doc);
+        if tf > 0.0 {
+            factors.push(("delta", self.delta));
+        }
+        factors
+    }
+}
+
+/// The BM25 form of IDF, `ln(1 + (N - df + 0.5) / (df + 0.5))`, which is always positive.
+fn bm25_idf(model: &Model, stats: &TermStats) -> f64 {
+    let n_docs: f64 = model.documents.len() as f64;
+    let df: f64 = f64::from(stats.doc_freq);
+
+    (1.0 + (n_docs - df + 0.5) / (df + 0.5)).ln()
+}
+
+/// The saturated, length normalised term frequency of BM25.
+fn bm25_tf(k1: f64, b: f64, model: &Model, tf: f64, doc: usize) -> f64 {
+    tf * (k1 + 1.0) / (tf + k1 * (1.0 - b + b * length_ratio(model, doc)))
+}
+
+/// The length of a document over the average length.
+fn length_ratio(model: &Model, doc: usize) -> f64 {
+    if model.average_length > 0.0 {
+        f64::from(model.documents[doc].length) / model.average_length
+    } else {
+        1.0
+    }
+}
+
+fn explain_bm25(k1: f64, b: f64, model: &Model, stats: &TermStats, tf: f64, doc: usize) -> Factors {
+    if tf == 0.0 {
+        return Vec::new();
+    }
+
+    vec![
+        ("idf", bm25_idf(model, stats)),
+        ("length ratio", length_ratio(model, doc)),
+        ("saturated tf", bm25_tf(k1, b, model, tf, doc)),
+    ]
+}
+
+/// Query likelihood with Dirichlet prior smoothing.
+///
+/// Documents are ranked by the log probability of generating the query from a language model of
+/// the document, mixed with the collection's model in proportion to `mu` pseudo-counts. Short
+/// documents lean more heavily on the collection statistics than long ones.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirichletLm {
+    pub mu: f64,
+}
+
+impl Default for DirichletLm {
+    fn default() -> Self {
+        Self { mu: 2000.0 }
+    }
+}
+
+impl Scorer for DirichletLm {
+    fn boosts_fields(&self) -> bool {
+        false
+    }
+
+    fn term_score(&self, model: &Model, stats: &TermStats, tf: f64, doc: usize) -> f64 {
+        let p_collection: f64 = collection_probability(model, stats);
+        if p_collection == 0.0 {
+            return 0.0;
+        }
+
+        let length: f64 = f64::from(model.documents[doc].length);
+        ((tf + self.mu * p_collection) / (length + self.mu)).ln()
+    }
+
+    fn explain_term(&self, model: &Model, stats: &TermStats, _tf: f64, doc: usize) -> Factors {
+        vec![
+            (
+                "collection probability",
+                collection_probability(model, stats),
+            ),
+            ("document length", f64::from(model.documents[doc].length)),
+        ]
+    }
+}
+
+/// Query likelihood with Jelinek-Mercer smoothing: the document's language model is linearly
+/// interpolated with the collection's, with weight `lambda` on the collection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JelinekMercerLm {
+    pub lambda: f64,
+}
+
+impl Default for JelinekMercerLm {
+    fn default() -> Self {
+        Self { lambda: 0.1 }
+    }
+}
+
+impl Scorer for JelinekMercerLm {
+    fn boosts_fields(&self) -> bool {
+        false
+    }
+
+    fn term_score(&self, model: &Model, stats: &TermStats, tf: f64, doc: usize) -> f64 {
+        let p_collection: f64 = collection_probability(model, stats);
+        if p_collection == 0.0 {
+            return 0.0;
+        }
+
+        let length: f64 = f64::from(model.documents[doc].length);
+        let p_document: f64 = if length > 0.0 { tf / length } else { 0.0 };
+        ((1.0 - self.lambda) * p_document + self.lambda * p_collection).ln()
+    }
+
+    fn explain_term(&self, model: &Model, stats: &TermStats, tf: f64, doc: usize) -> Factors {
+        let length: f64 = f64::from(model.documents[doc].length);
+        vec![
+            (
+                "collection probability",
+                collection_probability(model, stats),
+            ),
+            (
+                "document probability",
+                if length > 0.0 { tf / length } else { 0.0 },
+            ),
+        ]
+    }
+}
+
+/// Maximum likelihood estimate of the probability of a term in the whole collection.
+fn collection_probability(model: &Model, stats: &TermStats) -> f64 {
+    let collection_length: f64 = model.average_length * model.documents.len() as f64;
+    if collection_length == 0.0 {
+        return 0.0;
+    }
+
+    stats.collection_freq as f64 / collection_length
+}
+
+/// Boosts the score of another ranking function for documents in which the query terms occur
+/// close together.
+///
+/// Closeness is the number of query terms found in a document over the length of the shortest
+/// window containing all of them, so it is 1 for adjacent terms and approaches 0 as they move
+/// apart. The score is raised by `weight` times its magnitude scaled by closeness, which works
+/// the same way for similarities and (negative) log probabilities. Documents matching fewer than
+/// two query terms are left as they are. The weight must be less than 1, so that a negative score
+/// is never raised to zero or above.
+pub struct Proximity {
+    pub scorer: Box<dyn Scorer>,
+    pub weight: f64,
+}
+
+impl Proximity {
+    /// Closeness of the query terms in a document, between 0 and 1.
+    pub fn closeness(query: &[QueryTerm], doc: usize) -> f64 {
+        let positions: Vec<&[u32]> = query
+            .iter()
+            .filter_map(|term| query::find_posting(&term.matches, doc))
+            .map(|posting| posting.positions.as_slice())
+            // A word may only occur in a field without positions in the body.
+            .filter(|positions| !positions.is_empty())
+            .collect();
+
+        if positions.len() < 2 {
+            return 0.0;
+        }
+
+        match calculations::min_span(&positions) {
+            // Terms can share positions (e.g. a word and a phrase starting with it).
+            Some(span) => (positions.len() as f64 / f64::from(span)).min(1.0),
+            None => 0.0,
+        }
+    }
+}
+
+impl Scorer for Proximity {
+    fn boosts_fields(&self) -> bool {
+        self.scorer.boosts_fields()
+    }
+
+    fn term_score(&self, model: &Model, stats: &TermStats, tf: f64, doc: usize) -> f64 {
+        self.scorer.term_score(model, stats, tf, doc)
+    }
+
+    fn normalise(&self, model: &Model, query: &[QueryTerm], doc: usize, score: f64) -> f64 {
+        let score: f64 = self.scorer.normalise(model, query, doc, score);
+        score + self.weight * Self::closeness(query, doc) * score.abs()
+    }
+
+    fn explain_term(&self, model: &Model, stats: &TermStats, tf: f64, doc: usize) -> Factors {
+        self.scorer.explain_term(model, stats, tf, doc)
+    }
+
+    fn explain_normalise(
+        &self,
+        model: &Model,
+        query: &[QueryTerm],
+        doc: usize,
+        score: f64,
+    ) -> Factors {
+        let mut factors = self.scorer.explain_normalise(model, query, doc, score);
+        let score: f64 = self.scorer.normalise(model, query, doc, score);
+        let closeness: f64 = Self::closeness(query, doc);
+        factors.extend([
+            ("before proximity", score),
+            ("closeness", closeness),
+            ("proximity boost", self.weight * closeness * score.abs()),
+        ]);
+        factors
+    }
+}
+
+/// Parameters of the ranking functions which can be chosen by name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScorerOptions {
+    pub k1: f64,
+    pub b: f64,
+    pub delta: f64,
+    pub mu: f64,
+    pub lambda: f64,
+    /// Weight of the proximity boost; zero turns it off.
+    pub proximity: f64,
+}
+
+impl Default for ScorerOptions {
+    fn default() -> Self {
+        let bm25_plus = Bm25Plus::default();
+        Self {
+            k1: bm25_plus.k1,
+            b: bm25_plus.b,
+            delta: bm25_plus.delta,
+            mu: DirichletLm::default().mu,
+            lambda: JelinekMercerLm::default().lambda,
+            proximity: 0.5,
+        }
+    }
+}
+
+/// Names accepted by `scorer_from_name`.
+pub const SCORER_NAMES: [&str; 5] = ["tfidf", "bm25", "bm25+", "dirichlet", "jm"];
+
+/// Create a ranking function from its name, with a proximity boost unless it is turned off.
+pub fn scorer_from_name(name: &str, options: &ScorerOptions) -> Result<Box<dyn Scorer>, String> {
+    let scorer = base_scorer_from_name(name, options)?;
+
+    if !(0.0..1.0).contains(&options.proximity) {
+        return Err(format!(
+            "The proximity weight must be at least 0 and less than 1 (not {}).",
+            options.proximity
+        ));
+    }
+    if options.proximity > 0.0 {
+        Ok(Box::new(Proximity {
+            scorer,
+            weight: options.proximity,
+        }))
+    } else {
+        Ok(scorer)
+    }
+}
+
+fn base_scorer_from_name(name: &str, options: &ScorerOptions) -> Result<Box<dyn Scorer>, String> {
+    match name {
+        "tfidf" => Ok(Box::new(CosineTfIdf)),
+        "bm25" => Ok(Box::new(Bm25 {
+            k1: options.k1,
+            b: options.b,
+        })),
+        "bm25+" => Ok(Box::new(Bm25Plus {
+            k1: options.k1,
+            b: options.b,
+            delta: options.delta,
+        })),
+        // The logs of the query likelihood models are only defined for these.
+        "dirichlet" if options.mu <= 0.0 => Err(format!(
+            "The mu of the Dirichlet ranker must be greater than 0 (not {}).",
+            options.mu
+        )),
+        "jm" if !(options.lambda > 0.0 && options.lambda <= 1.0) => Err(format!(
+            "The lambda of the Jelinek-Mercer ranker must be greater than 0 and at most 1 (not {}).",
+            options.lambda
+        )),
+        "dirichlet" => Ok(Box::new(DirichletLm { mu: options.mu })),
+        "jm" => Ok(Box::new(JelinekMercerLm {
+            lambda: options.lambda,
+        })),
+        _ => Err(format!(
+            "Unknown ranker '{name}' (expected one of: {}).",
+            SCORER_NAMES.join(", ")
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::DocumentInfo;
+    use crate::query::PreparedQuery;
+    use crate::text_process::{tokens_from_words, Token};
+
+    fn sky_stats(model: &Model) -> TermStats {
+        QueryTerm::from_term(model, model.vocabulary.id("sky").unwrap()).stats
+    }
+
+    fn test_model() -> Model {
+        let corpus: Vec<Vec<Token>> = [
+            vec!["sky".to_string(), "blue".to_string()],
+            vec!["sun".to_string(), "bright".to_string()],
+            vec![
+                "sky".to_string(),
+                "sky".to_string(),
+                "sun".to_string(),
+                "moon".to_string(),
+                "star".to_string(),
+                "cloud".to_string(),
+            ],
+        ]
+        .iter()
+        .map(|words| tokens_from_words(words))
+        .collect();
+        let documents: Vec<DocumentInfo> = corpus
+            .iter()
+            .map(|doc| DocumentInfo {
+                length: doc.len() as u32,
+                ..Default::default()
+            })
+            .collect();
+        Model::new(documents, &corpus)
+    }
+
+    #[test]
+    fn test_bm25_term_score() {
+        let model = test_model();
+        let sky = sky_stats(&model);
+        let scorer = Bm25::default();
+
+        // N = 3, df = 2, average length = 10 / 3.
+        let idf = (1.0f64 + 1.5 / 2.5).ln();
+        let expected = idf * 2.2 / (1.0 + 1.2 * (0.25 + 0.75 * 2.0 / (10.0 / 3.0)));
+
+        let result = scorer.term_score(&model, &sky, 1.0, 0);
+
+        assert!((result - expected).abs() < 1e-12);
+        assert_eq!(scorer.term_score(&model, &sky, 0.0, 1), 0.0);
+    }
+
+    #[test]
+    fn test_bm25_plus_adds_delta() {
+        let model = test_model();
+        let sky = sky_stats(&model);
+
+        let bm25 = Bm25::default().term_score(&model, &sky, 2.0, 2);
+        let bm25_plus = Bm25Plus::default().term_score(&model, &sky, 2.0, 2);
+
+        let idf = (1.0f64 + 1.5 / 2.5).ln();
+        assert!((bm25_plus - bm25 - idf).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_bm25_length_normalisation() {
+        let model = test_model();
+        let sky = sky_stats(&model);
+        let scorer = Bm25::default();
+
+        // The same term frequency counts for less in a longer document.
+        assert!(scorer.term_score(&model, &sky, 1.0, 0) > scorer.term_score(&model, &sky, 1.0, 2));
+    }
+
+    #[test]
+    fn test_dirichlet_term_score() {
+        let model = test_model();
+        let sky = sky_stats(&model);
+        let scorer = DirichletLm { mu: 10.0 };
+
+        // "sky" is 3 of the 10 tokens in the collection.
+        let result = scorer.term_score(&model, &sky, 1.0, 0);
+        assert!((result - ((1.0f64 + 3.0) / 12.0).ln()).abs() < 1e-12);
+
+        // A missing term is smoothed by the collection rather than scoring zero.
+        let result = scorer.term_score(&model, &sky, 0.0, 1);
+        assert!((result - (3.0f64 / 12.0).ln()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_jelinek_mercer_term_score() {
+        let model = test_model();
+        let sky = sky_stats(&model);
+        let scorer = JelinekMercerLm { lambda: 0.5 };
+
+        let result = scorer.term_score(&model, &sky, 2.0, 2);
+        assert!((result - (0.5f64 * 2.0 / 6.0 + 0.5 * 0.3).ln()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_query_likelihood_prefers_short_matching_documents() {
+        let model = test_model();
+        let query = PreparedQuery::from_terms(vec![QueryTerm::from_term(
+            &model,
+            model.vocabulary.id("sky").unwrap(),
+        )]);
+
+        let result = calculations::rank_documents(&query, &model, &DirichletLm { mu: 10.0 });
+
+        // tf / length is 1/2 in the first document and 2/6 in the third.
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].0, 0);
+        assert!(result.iter().all(|&(_, score)| score < 0.0));
+    }
+
+    #[test]
+    fn test_proximity_prefers_close_terms() {
+        let corpus: Vec<Vec<Token>> = [
+            ["sun", "moon", "star", "cloud", "sky", "blue"],
+            ["sky", "sun", "moon", "star", "cloud", "blue"],
+        ]
+        .iter()
+        .map(|words| tokens_from_words(&words.map(String::from)))
+        .collect();
+        let documents: Vec<DocumentInfo> = vec![
+            DocumentInfo {
+                length: 6,
+                ..Default::default()
+            };
+            2
+        ];
+        let model = Model::new(documents, &corpus);
+        let query = PreparedQuery::from_terms(
+            ["sky", "blue"]
+                .iter()
+                .map(|word| QueryTerm::from_term(&model, model.vocabulary.id(word).unwrap()))
+                .collect(),
+        );
+
+        assert_eq!(Proximity::closeness(&query.terms, 0), 1.0);
+        assert!((Proximity::closeness(&query.terms, 1) - 2.0 / 6.0).abs() < 1e-12);
+
+        let scorer = Proximity {
+            scorer: Box::new(Bm25::default()),
+            weight: 0.5,
+        };
+        let result = calculations::rank_documents(&query, &model, &scorer);
+        let base = calculations::rank_documents(&query, &model, &Bm25::default());
+
+        // Both documents score the same without the boost.
+        assert_eq!(base[0].1, base[1].1);
+        assert_eq!(result[0].0, 0);
+        assert!((result[0].1 - 1.5 * base[0].1).abs() < 1e-12);
+        assert!(result[1].1 < result[0].1);
+    }
+
+    #[test]
+    fn test_scorer_from_name() {
+        assert!(scorer_from_name("bm25", &ScorerOptions::default()).is_ok());
+        assert_eq!(
+            Some(String::from(
+                "Unknown ranker 'pagerank' (expected one of: tfidf, bm25, bm25+, dirichlet, jm)."
+            )),
+            scorer_from_name("pagerank", &ScorerOptions::default()).err()
+        );
+    }
+
+    #[test]
+    fn test_proximity_keeps_log_probabilities_negative() {
+        let corpus: Vec<Vec<Token>> = [
+            ["sky", "blue", "sun", "moon"],
+            ["sky", "sun", "moon", "star"],
+        ]
+        .iter()
+        .map(|words| tokens_from_words(&words.map(String::from)))
+        .collect();
+        let documents: Vec<DocumentInfo> = vec![
+            DocumentInfo {
+                length: 4,
+                ..Default::default()
+            };
+            2
+        ];
+        let model = Model::new(documents, &corpus);
+        let query = PreparedQuery::from_terms(
+            ["sky", "blue"]
+                .iter()
+                .map(|word| QueryTerm::from_term(&model, model.vocabulary.id(word).unwrap()))
+                .collect(),
+        );
+        let options = |proximity: f64| ScorerOptions {
+            mu: 10.0,
+            proximity,
+            ..Default::default()
+        };
+
+        // The first document has both words side by side, the second only one of them.
+        let scorer = scorer_from_name("dirichlet", &options(0.99)).unwrap();
+        let result = calculations::rank_documents(&query, &model, scorer.as_ref());
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].0, 0);
+        assert!(result.iter().all(|&(_, score)| score < 0.0));
+
+        assert!(scorer_from_name("dirichlet", &options(1.0)).is_err());
+        assert!(scorer_from_name("jm", &options(-0.5)).is_err());
+    }
+
+    #[test]
+    fn test_query_likelihood_in_title() {
+        // `title: Rust Rust` above a body of `rust`, as indexed by `loading::extract_tokens`.
+        let corpus: Vec<Vec<Token>> = [
+            vec!["rust", "title:rust", "title:rust", "path:notes"],
+            vec!["sky", "path:sky"],
+        ]
+        .iter()
+        .map(|words| {
+            tokens_from_words(
+                &words
+                    .iter()
+                    .map(|word| word.to_string())
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .collect();
+        let documents: Vec<DocumentInfo> = corpus
+            .iter()
+            .map(|doc| DocumentInfo {
+                length: doc.len() as u32,
+                ..Default::default()
+            })
+            .collect();
+        let model = Model::new(documents, &corpus);
+        let query = PreparedQuery::from_terms(vec![QueryTerm::from_word(&model, "rust").unwrap()]);
+
+        // Each occurrence counts once, so the probabilities are at most 1.
+        for name in ["dirichlet", "jm"] {
+            let scorer = scorer_from_name(name, &ScorerOptions::default()).unwrap();
+            let explanation = calculations::explain_document(&query, &model, scorer.as_ref(), 0);
+            assert_eq!(explanation.terms[0].tf, 3.0, "{name}");
+            assert!(explanation.terms[0].score <= 0.0, "{name}");
+        }
+        // Other rankers still boost the title.
+        let explanation = calculations::explain_document(&query, &model, &Bm25::default(), 0);
+        assert_eq!(explanation.terms[0].tf, 7.0);
+    }
+
+    #[test]
+    fn test_scorer_from_name_smoothing() {
+        let options = |mu: f64, lambda: f64| ScorerOptions {
+            mu,
+            lambda,
+            ..Default::default()
+        };
+
+        assert!(scorer_from_name("dirichlet", &options(0.0, 0.1)).is_err());
+        assert!(scorer_from_name("dirichlet", &options(-5.0, 0.1)).is_err());
+        assert!(scorer_from_name("dirichlet", &options(1.0, 0.1)).is_ok());
+        assert!(scorer_from_name("jm", &options(2000.0, 0.0)).is_err());
+        assert!(scorer_from_name("jm", &options(2000.0, 1.5)).is_err());
+        assert!(scorer_from_name("jm", &options(2000.0, f64::NAN)).is_err());
+        assert!(scorer_from_name("jm", &options(2000.0, 1.0)).is_ok());
+        // Each only checks its own parameter.
+        assert!(scorer_from_name("bm25", &options(0.0, 0.0)).is_ok());
+    }
+}
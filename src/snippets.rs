@@ -0,0 +1,335 @@
+use super::model::DocumentInfo;
+use super::passages::{split_passages, Splitting};
+use super::text_process::Analyzer;
+use std::collections::BTreeSet;
+use std::fs;
+use std::ops::Range;
+
+/// Words of the text on either side of a match in a snippet.
+const CONTEXT: usize = 10;
+
+/// A part of a file around the words a query looks for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snippet {
+    /// The line of the file the snippet starts on (1-based).
+    pub line: u32,
+    /// The text of the snippet on one line, with `…` where it cuts into a line.
+    pub text: String,
+    /// The bytes of `text` holding the words of the query.
+    pub highlights: Vec<Range<usize>>,
+}
+
+impl Snippet {
+    /// The text with each highlight put between `open` and `close`, e.g. ANSI colour codes or
+    /// `<mark>` and `</mark>`.
+    pub fn marked(&self, open: &str, close: &str) -> String {
+        let mut marked = String::new();
+        let mut end = 0;
+        for highlight in &self.highlights {
+            marked.push_str(&self.text[end..highlight.start]);
+            marked.push_str(open);
+            marked.push_str(&self.text[highlight.clone()]);
+            marked.push_str(close);
+            end = highlight.end;
+        }
+        marked.push_str(&self.text[end..]);
+        marked
+    }
+}
+
+/// Find up to `count` snippets of a text where the words occur most densely, in the order they
+/// come in the text.
+///
+/// The text is tokenised again by the analyzer, without its character filters so that each token
+/// keeps the bytes it was read from, and a token matches if its analysed text is one of the
+/// words. Each snippet is a window of the words of the text, split at whitespace, around a match.
+/// Windows are scored by how many of the words they hold and then by how many matches, and the
+/// best ones which do not overlap are kept.
+pub fn find_snippets(
+    text: &str,
+    words: &BTreeSet<String>,
+    analyzer: &Analyzer,
+    count: usize,
+) -> Vec<Snippet> {
+    if words.is_empty() || count == 0 {
+        return Vec::new();
+    }
+
+    let mut text_words: Vec<Word> = whitespace_words(text)
+        .into_iter()
+        .map(|span| Word {
+            span,
+            found: Vec::new(),
+        })
+        .collect();
+    for token in analyzer.analyse_source(text) {
+        if let Some(word) = words.get(&token.text) {
+            let i = text_words.partition_point(|word| word.span.end <= token.span.start);
+            if let Some(text_word) = text_words.get_mut(i) {
+                text_word.found.push((token.span, word.as_str()));
+            }
+        }
+    }
+
+    let score = |window: &Range<usize>| {
+        let window = &text_words[window.clone()];
+        let distinct: BTreeSet<&str> = window
+            .iter()
+            .flat_map(|text_word| text_word.found.iter().map(|(_, word)| *word))
+            .collect();
+        let matches = window
+            .iter()
+            .filter(|text_word| !text_word.found.is_empty());
+        (distinct.len(), matches.count())
+    };
+    let mut ranked: Vec<(Range<usize>, (usize, usize))> = text_words
+        .iter()
+        .enumerate()
+        .filter(|(_, text_word)| !text_word.found.is_empty())
+        .map(|(i, _)| {
+            let window = i.saturating_sub(CONTEXT)..(i + CONTEXT + 1).min(text_words.len());
+            let score = score(&window);
+            (window, score)
+        })
+        .collect();
+    // Stable, so that of equal windows the first in the text is taken.
+    ranked.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+
+    let mut chosen: Vec<Range<usize>> = Vec::new();
+    for (window, _) in ranked {
+        if chosen.len() == count {
+            break;
+        }
+        if chosen
+            .iter()
+            .all(|other| window.end <= other.start || other.end <= window.start)
+        {
+            chosen.push(window);
+        }
+    }
+    chosen.sort_by_key(|window| window.start);
+
+    chosen
+        .into_iter()
+        .map(|window| snippet(text, &text_words[window]))
+        .collect()
+}
+
+/// A word of a text, split at whitespace, with the tokens in it which match the words of a
+/// query.
+struct Word<'a> {
+    span: Range<usize>,
+    found: Vec<(Range<usize>, &'a str)>,
+}
+
+/// The bytes of each word of a text, split at whitespace.
+fn whitespace_words(text: &str) -> Vec<Range<usize>> {
+    let mut words = Vec::new();
+    let mut start: Option<usize> = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(word)) => {
+                words.push(word..i);
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => {}
+        }
+    }
+    words.extend(start.map(|word| word..text.len()));
+    words
+}
+
+/// Make a snippet from a window of the words of a text, joined by single spaces.
+fn snippet(text: &str, words: &[Word]) -> Snippet {
+    let start = words[0].span.start;
+    let end = words[words.len() - 1].span.end;
+    let line_start = text[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = text[end..].find('\n').map_or(text.len(), |i| end + i);
+
+    let mut snippet = Snippet {
+        line: text[..start].matches('\n').count() as u32 + 1,
+        text: String::new(),
+        highlights: Vec::new(),
+    };
+    if !text[line_start..start].trim().is_empty() {
+        snippet.text.push_str("… ");
+    }
+    for (i, Word { span, found }) in words.iter().enumerate() {
+        if i > 0 {
+            snippet.text.push(' ');
+        }
+        let base = snippet.text.len();
+        snippet.text.push_str(&text[span.clone()]);
+
+        // The widest of the matches which overlap, e.g. a word and its parts.
+        let mut found: Vec<&Range<usize>> = found.iter().map(|(range, _)| range).collect();
+        found.sort_by_key(|range| (range.start, std::cmp::Reverse(range.end)));
+        let mut last_end = 0;
+        for range in found {
+            if range.start >= last_end {
+                snippet
+                    .highlights
+                    .push(base + range.start - span.start..base + range.end - span.start);
+                last_end = range.end;
+            }
+        }
+    }
+    if !text[end..line_end].trim().is_empty() {
+        snippet.text.push_str(" …");
+    }
+    snippet
+}
+
+/// Find snippets of a document in its file, within the lines of its passage if it is one, and
+/// otherwise below its front matter. No snippets are found if the file cannot be read.
+pub fn document_snippets(
+    document: &DocumentInfo,
+    words: &BTreeSet<String>,
+    analyzer: &Analyzer,
+    count: usize,
+) -> Vec<Snippet> {
+    let Ok(contents) = fs::read_to_string(&document.path) else {
+        return Vec::new();
+    };
+
+    let (text, first_line) = match &document.passage {
+        None => match split_passages(&contents, Splitting::Document).first() {
+            Some((passage, text)) => (text.to_string(), passage.start_line),
+            None => return Vec::new(),
+        },
+        Some(passage) => {
+            let skip = passage.start_line.saturating_sub(1);
+            let lines: Vec<&str> = contents
+                .split_inclusive('\n')
+                .skip(skip as usize)
+                .take(passage.end_line.saturating_sub(skip) as usize)
+                .collect();
+            (lines.concat(), passage.start_line)
+        }
+    };
+
+    let mut snippets = find_snippets(&text, words, analyzer, count);
+    for snippet in &mut snippets {
+        snippet.line += first_line.saturating_sub(1);
+    }
+    snippets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::passages::Passage;
+
+    fn words(words: &[&str]) -> BTreeSet<String> {
+        words.iter().map(|word| word.to_string()).collect()
+    }
+
+    #[test]
+    fn test_find_snippets() {
+        let text = "# Ownership\n\nEach value has one owner. You may borrow it.\n\n\
+            Ownership rules are checked at compile time by the compiler, which reports every \
+            mistake.\nThe borrow checker rejects code that breaks either rule.\n";
+
+        let result = find_snippets(
+            text,
+            &words(&["borrow", "checker"]),
+            &Analyzer::default(),
+            1,
+        );
+
+        // The last line holds both words, with ten words of context before it.
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].line, 5);
+        assert_eq!(
+            result[0].marked("[", "]"),
+            "… compile time by the compiler, which reports every mistake. \
+            The [borrow] [checker] rejects code that breaks either rule."
+        );
+    }
+
+    #[test]
+    fn test_snippets_do_not_overlap() {
+        let filler = "word ".repeat(30);
+        let text = format!("rust here\n{filler}\nand **Rust** there.\n");
+
+        let result = find_snippets(&text, &words(&["rust"]), &Analyzer::default(), 3);
+
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].line, 1);
+        assert!(result[0].text.starts_with("rust here word"));
+        assert!(result[0].text.ends_with("word …"));
+        assert_eq!(result[0].highlights, vec![0..4]);
+        // The window before the match starts within the filler line.
+        assert_eq!(result[1].line, 2);
+        assert!(result[1]
+            .marked("<mark>", "</mark>")
+            .ends_with("word and **<mark>Rust</mark>** there."));
+        assert!(find_snippets(&text, &words(&["python"]), &Analyzer::default(), 3).is_empty());
+    }
+
+    #[test]
+    fn test_document_snippets() {
+        let dir = std::env::temp_dir().join(format!("sesquioxide-snippets-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notes.md").to_string_lossy().to_string();
+        fs::write(
+            &path,
+            "---\ntitle: Sky\n---\n# Sky\n\nThe sky is blue.\n\n# Sun\n\nThe sun is bright, like the sky.\n",
+        )
+        .unwrap();
+        let words = words(&["sky"]);
+        let analyzer = Analyzer::default();
+
+        // The front matter is left out.
+        let document = DocumentInfo {
+            path: path.clone(),
+            ..Default::default()
+        };
+        let result = document_snippets(&document, &words, &analyzer, 1);
+        assert_eq!(result[0].line, 4);
+        assert_eq!(
+            result[0].text,
+            "# Sky The sky is blue. # Sun The sun is bright, …"
+        );
+
+        // Only the lines of a passage are searched, and numbered as in the file.
+        let passage = DocumentInfo {
+            path,
+            passage: Some(Passage {
+                start_line: 8,
+                end_line: 10,
+                section: Some(String::from("Sun")),
+            }),
+            ..Default::default()
+        };
+        let result = document_snippets(&passage, &words, &analyzer, 2);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].line, 8);
+        assert!(result[0].text.ends_with("like the sky."));
+
+        // A passage ending before it starts, e.g. from a stale index, has no lines.
+        let stale = DocumentInfo {
+            passage: Some(Passage {
+                start_line: 8,
+                end_line: 0,
+                section: None,
+            }),
+            ..passage.clone()
+        };
+        assert!(document_snippets(&stale, &words, &analyzer, 2).is_empty());
+        // Nor is one starting at line 0 numbered before the first line.
+        let stale = DocumentInfo {
+            passage: Some(Passage {
+                start_line: 0,
+                end_line: 4,
+                section: None,
+            }),
+            ..passage
+        };
+        let result = document_snippets(&stale, &words, &analyzer, 1);
+        assert_eq!(result[0].line, 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,476 @@
+use super::document::{Date, Document, Heading, Value};
+use super::model::{DocumentInfo, Model, Posting};
+use super::passages::{Passage, Splitting};
+use super::text_process::Analyzer;
+use super::vocabulary::Vocabulary;
+use std::fs;
+
+/// Identifies a sesquioxide index file.
+const MAGIC: &[u8; 8] = b"SESQIDX\0";
+
+/// Version of the index file layout written by this build.
+pub const FORMAT_VERSION: u32 = 18;
+
+/// Name of the index file written into an indexed directory by default.
+pub const DEFAULT_INDEX_NAME: &str = ".sesquioxide.idx";
+
+/// Appends little-endian values to a byte buffer.
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn f64(&mut self, value: f64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn str(&mut self, value: &str) {
+        self.u64(value.len() as u64);
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn strs<S: AsRef<str>>(&mut self, values: &[S]) {
+        self.u64(values.len() as u64);
+        for value in values {
+            self.str(value.as_ref());
+        }
+    }
+
+    /// Write the metadata of a document (see `Reader::document`).
+    fn document(&mut self, document: &Document) {
+        self.strs(document.title.as_slice());
+        self.u64(document.headings.len() as u64);
+        for heading in &document.headings {
+            self.u32(u32::from(heading.level));
+            self.str(&heading.text);
+        }
+        self.strs(&document.links);
+        self.strs(&document.images);
+        self.strs(&document.code_languages);
+        self.u64(document.front_matter.len() as u64);
+        for (key, value) in &document.front_matter {
+            self.str(key);
+            match value {
+                Value::Text(text) => {
+                    self.u32(0);
+                    self.str(text);
+                }
+                Value::List(items) => {
+                    self.u32(1);
+                    self.strs(items);
+                }
+                Value::Number(number) => {
+                    self.u32(2);
+                    self.f64(*number);
+                }
+                Value::Date(date) => {
+                    self.u32(3);
+                    self.u32(u32::from(date.year));
+                    self.u32(u32::from(date.month));
+                    self.u32(u32::from(date.day));
+                }
+            }
+        }
+    }
+
+    /// Write where a document is in its file, if it is a passage (see `Reader::passage`).
+    fn passage(&mut self, passage: Option<&Passage>) {
+        match passage {
+            None => self.u32(0),
+            Some(passage) => {
+                self.u32(1);
+                self.u32(passage.start_line);
+                self.u32(passage.end_line);
+                self.strs(passage.section.as_slice());
+            }
+        }
+    }
+}
+
+/// Reads little-endian values back out of a byte buffer.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() < n {
+            return Err(String::from("Index file is truncated."));
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    fn f64(&mut self) -> Result<f64, String> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Ok(f64::from_le_bytes(buf))
+    }
+
+    /// Read a length or count, refusing values that could not fit in the remaining bytes.
+    fn len(&mut self) -> Result<usize, String> {
+        let len = self.u64()?;
+        if len > self.bytes.len() as u64 {
+            return Err(String::from("Index file is corrupt."));
+        }
+        Ok(len as usize)
+    }
+
+    fn str(&mut self) -> Result<String, String> {
+        let len = self.len()?;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| String::from("Index file is corrupt."))
+    }
+
+    fn strs(&mut self) -> Result<Vec<String>, String> {
+        let len = self.len()?;
+        (0..len).map(|_| self.str()).collect()
+    }
+
+    /// Read the metadata of a document: its title (a list of none or one), headings, links,
+    /// image alt text, code languages and front matter.
+    fn document(&mut self) -> Result<Document, String> {
+        let title = self.strs()?.pop();
+        let n_headings = self.len()?;
+        let headings = (0..n_headings)
+            .map(|_| {
+                let level = u8::try_from(self.u32()?)
+                    .map_err(|_| String::from("Index file is corrupt."))?;
+                Ok(Heading {
+                    level,
+                    text: self.str()?,
+                })
+            })
+            .collect::<Result<Vec<Heading>, String>>()?;
+        let links = self.strs()?;
+        let images = self.strs()?;
+        let code_languages = self.strs()?;
+        let n_entries = self.len()?;
+        let front_matter = (0..n_entries)
+            .map(|_| {
+                let key = self.str()?;
+                let value = match self.u32()? {
+                    0 => Value::Text(self.str()?),
+                    1 => Value::List(self.strs()?),
+                    2 => Value::Number(self.f64()?),
+                    3 => {
+                        let corrupt = |_| String::from("Index file is corrupt.");
+                        Value::Date(Date {
+                            year: u16::try_from(self.u32()?).map_err(corrupt)?,
+                            month: u8::try_from(self.u32()?).map_err(corrupt)?,
+                            day: u8::try_from(self.u32()?).map_err(corrupt)?,
+                        })
+                    }
+                    _ => return Err(String::from("Index file is corrupt.")),
+                };
+                Ok((key, value))
+            })
+            .collect::<Result<Vec<(String, Value)>, String>>()?;
+
+        Ok(Document {
+            title,
+            headings,
+            links,
+            images,
+            code_languages,
+            front_matter,
+        })
+    }
+
+    /// Read where a document is in its file: a flag for whether it is a passage, then its first
+    /// and last lines and its section (a list of none or one).
+    fn passage(&mut self) -> Result<Option<Passage>, String> {
+        match self.u32()? {
+            0 => Ok(None),
+            1 => Ok(Some(Passage {
+                start_line: self.u32()?,
+                end_line: self.u32()?,
+                section: self.strs()?.pop(),
+            })),
+            _ => Err(String::from("Index file is corrupt.")),
+        }
+    }
+}
+
+/// Serialise a model into the binary index format.
+///
+/// Layout (all integers little-endian): magic, format version, the config of the analyzer (see
+/// `Analyzer::from_config`) and of the splitting into passages (see `Splitting::from_config`),
+/// the directory indexed, the document table (with the metadata and passage of each document), then for each term in
+/// id order its text, collection and document frequencies, IDF and postings list.
+pub fn encode(model: &Model) -> Vec<u8> {
+    let mut out = Writer { bytes: Vec::new() };
+
+    out.bytes.extend_from_slice(MAGIC);
+    out.u32(FORMAT_VERSION);
+    out.str(&model.analyzer.config());
+    out.str(&model.splitting.config());
+    out.str(&model.root);
+
+    out.u64(model.documents.len() as u64);
+    for doc in &model.documents {
+        out.str(&doc.path);
+        out.u32(doc.length);
+        out.u64(doc.mtime);
+        out.u64(doc.size);
+        out.u64(doc.hash);
+        out.document(&doc.metadata);
+        out.passage(doc.passage.as_ref());
+    }
+
+    out.u64(model.vocabulary.len() as u64);
+    for (id, list) in model.postings.iter().enumerate() {
+        out.str(model.vocabulary.term(id));
+        out.u64(model.vocabulary.collection_freq(id));
+        out.u32(model.vocabulary.doc_freq(id));
+        out.f64(model.idf[id]);
+        out.u64(list.len() as u64);
+        for posting in list {
+            out.u32(posting.doc as u32);
+            out.f64(posting.tf);
+            out.u64(posting.positions.len() as u64);
+            for &position in &posting.positions {
+                out.u32(position);
+            }
+        }
+    }
+
+    out.bytes
+}
+
+/// Read a model back from the binary index format.
+pub fn decode(bytes: &[u8]) -> Result<Model, String> {
+    let mut input = Reader { bytes };
+
+    if input.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+        return Err(String::from("Not a sesquioxide index file."));
+    }
+
+    match input.u32()? {
+        FORMAT_VERSION => decode_body(&mut input),
+        version if version > FORMAT_VERSION => Err(format!(
+            "Index file format version {version} is newer than this program supports \
+             ({FORMAT_VERSION}); please upgrade."
+        )),
+        // Earlier versions lack word positions, fields, the analyzer or the directory indexed,
+        // or cleaned up text differently, which can only be put right by reading the files
+        // again.
+        version => Err(format!(
+            "Index file format version {version} is no longer supported; please re-index."
+        )),
+    }
+}
+
+fn decode_body(input: &mut Reader) -> Result<Model, String> {
+    let analyzer: Analyzer = Analyzer::from_config(&input.str()?)
+        .map_err(|err| format!("The analyzer of the index file cannot be built. {err}"))?;
+    let splitting: Splitting = Splitting::from_config(&input.str()?)?;
+    let root: String = input.str()?;
+
+    let n_docs = input.len()?;
+    let mut documents: Vec<DocumentInfo> = Vec::with_capacity(n_docs);
+    for _ in 0..n_docs {
+        documents.push(DocumentInfo {
+            path: input.str()?,
+            length: input.u32()?,
+            mtime: input.u64()?,
+            size: input.u64()?,
+            hash: input.u64()?,
+            metadata: input.document()?,
+            passage: input.passage()?,
+        });
+    }
+
+    let n_terms = input.len()?;
+    let mut vocabulary = Vocabulary::new();
+    let mut postings: Vec<Vec<Posting>> = Vec::with_capacity(n_terms);
+    let mut idf: Vec<f64> = Vec::with_capacity(n_terms);
+    for _ in 0..n_terms {
+        let term = input.str()?;
+        let collection_freq = input.u64()?;
+        let doc_freq = input.u32()?;
+        vocabulary.insert_with_stats(&term, collection_freq, doc_freq);
+        idf.push(input.f64()?);
+
+        let n_postings = input.len()?;
+        let mut list: Vec<Posting> = Vec::with_capacity(n_postings);
+        for _ in 0..n_postings {
+            let doc = input.u32()? as usize;
+            if doc >= n_docs {
+                return Err(String::from("Index file is corrupt."));
+            }
+            let tf = input.f64()?;
+            let n_positions = input.len()?;
+            let positions: Vec<u32> = (0..n_positions)
+                .map(|_| input.u32())
+                .collect::<Result<Vec<u32>, String>>()?;
+            list.push(Posting { doc, tf, positions });
+        }
+        postings.push(list);
+    }
+
+    if vocabulary.len() != n_terms {
+        return Err(String::from("Index file is corrupt."));
+    }
+
+    let mut model = Model::from_parts(documents, vocabulary, postings, idf);
+    model.analyzer = analyzer;
+    model.splitting = splitting;
+    model.root = root;
+    Ok(model)
+}
+
+/// Write a model to an index file.
+pub fn save_index(model: &Model, path: &str) -> Result<(), String> {
+    fs::write(path, encode(model)).map_err(|err| format!("Could not write index file: {err}"))
+}
+
+/// Load a model from an index file.
+pub fn load_index(path: &str) -> Result<Model, String> {
+    let bytes = fs::read(path).map_err(|err| format!("Could not read index file: {err}"))?;
+    decode(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text_process::{self, Language, Stem, Token};
+
+    fn test_model() -> Model {
+        let corpus: Vec<Vec<Token>> = vec![
+            text_process::tokenise_positions("sky blue the sky").unwrap(),
+            text_process::tokenise_positions("sun bright").unwrap(),
+        ];
+        let documents: Vec<DocumentInfo> = vec![
+            DocumentInfo {
+                path: "doc1.txt".to_string(),
+                length: 3,
+                mtime: 10,
+                size: 13,
+                hash: 1,
+                metadata: Document::parse(
+                    "---\ntags: [sky]\nstatus: draft\ndate: 2025-03-01\npriority: 2\n---\n\
+                    # Sky\n```sh\nls\n```\n[link](a.md) ![alt](b.png)\n",
+                ),
+                passage: None,
+            },
+            DocumentInfo {
+                path: "doc2.txt".to_string(),
+                length: 2,
+                mtime: 20,
+                size: 10,
+                hash: 2,
+                passage: Some(Passage {
+                    start_line: 3,
+                    end_line: 5,
+                    section: Some("Sun".to_string()),
+                }),
+                ..Default::default()
+            },
+        ];
+        Model::new(documents, &corpus)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let model = test_model();
+
+        let result = decode(&encode(&model)).unwrap();
+
+        assert_eq!(result.documents, model.documents);
+        assert_eq!(result.documents[0].metadata.code_languages, vec!["sh"]);
+        assert_eq!(result.vocabulary, model.vocabulary);
+        assert_eq!(result.postings, model.postings);
+        assert_eq!(result.idf, model.idf);
+        assert_eq!(result.norms, model.norms);
+        assert_eq!(result.analyzer, Analyzer::default());
+    }
+
+    #[test]
+    fn test_round_trip_analyzer() {
+        let mut model = test_model();
+        model.analyzer = Analyzer::default().with_token_filter(Box::new(Stem {
+            language: Language::English,
+        }));
+
+        model.splitting = Splitting::Window {
+            size: 100,
+            overlap: 20,
+        };
+        model.root = String::from("notes");
+
+        let result = decode(&encode(&model)).unwrap();
+
+        assert_eq!(result.analyzer, model.analyzer);
+        assert_eq!(result.splitting, model.splitting);
+        assert_eq!(result.root, "notes");
+    }
+
+    #[test]
+    fn test_decode_not_an_index() {
+        let result = decode(b"some text");
+
+        assert_eq!(
+            Some(String::from("Not a sesquioxide index file.")),
+            result.err()
+        );
+    }
+
+    #[test]
+    fn test_decode_newer_version() {
+        let mut bytes = encode(&test_model());
+        bytes[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+
+        let result = decode(&bytes);
+
+        assert!(result
+            .err()
+            .unwrap()
+            .contains("newer than this program supports"));
+    }
+
+    #[test]
+    fn test_decode_old_version() {
+        let mut bytes = encode(&test_model());
+        bytes[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&2u32.to_le_bytes());
+
+        let result = decode(&bytes);
+
+        assert_eq!(
+            Some(String::from(
+                "Index file format version 2 is no longer supported; please re-index."
+            )),
+            result.err()
+        );
+    }
+
+    #[test]
+    fn test_decode_truncated() {
+        let bytes = encode(&test_model());
+
+        let result = decode(&bytes[..bytes.len() - 3]);
+
+        assert_eq!(Some(String::from("Index file is truncated.")), result.err());
+    }
+}
@@ -1,14 +1,50 @@
+use super::analysis::Tokenizer;
 use super::stop_words::STOP_WORDS;
 use regex::Regex;
-use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::LazyLock;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// A word from a text, with its position among the words of the text.
+///
+/// Positions count every word, including stop words which are not kept, so the gaps left by
+/// removed stop words are the same in documents and queries.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub text: String,
+    pub position: u32,
+    /// The bytes of the text given to the tokenizer which the word was read from (see
+    /// `Analyzer::analyse_source`). Words added by token filters have the span of the word they
+    /// were made from, or of their part of it.
+    pub span: Range<usize>,
+}
+
+/// The byte range of a slice of a text within it.
+fn span_in(text: &str, word: &str) -> Range<usize> {
+    let start = word.as_ptr() as usize - text.as_ptr() as usize;
+    start..start + word.len()
+}
+
+impl AsRef<str> for Token {
+    fn as_ref(&self) -> &str {
+        &self.text
+    }
+}
 
 /// Create a vector of tokens from a String of words.
 pub fn tokenise(text: &str) -> Result<Vec<String>, String> {
-    let words = text
-        .split(' ')
-        .filter(|s| !STOP_WORDS.contains(s) && !s.is_empty())
-        .map(String::from)
-        .collect::<Vec<String>>();
+    tokenise_positions(text).map(|tokens| tokens.into_iter().map(|token| token.text).collect())
+}
+
+/// Create a vector of tokens, with their positions, from a String of words.
+pub fn tokenise_positions(text: &str) -> Result<Vec<Token>, String> {
+    let words = UnicodeWords
+        .tokenise(text)
+        .into_iter()
+        .filter(|token| !STOP_WORDS.contains(&token.text.as_str()))
+        .collect::<Vec<Token>>();
 
     if words.is_empty() {
         return Err(String::from("No words found."));
@@ -17,48 +53,239 @@ pub fn tokenise(text: &str) -> Result<Vec<String>, String> {
     Ok(words)
 }
 
-/// Clean up the text in a string, removing certain 'code' elements.
-pub fn clean_up_text(text: &str) -> String {
-    let regex_strings = [
-        "[^a-zA-Z ]", // single letters
-        " +",         // more than one space
-        r"\$.*\$",    // math fields
-        // web links
-        r"https?://(www\.)?[-a-zA-Z0-9@:%._\+~#=]{1,256}\.[a-zA-Z0-9()]{1,6}\b([-a-zA-Z0-9()@:%_\+.~#?&//=]*)",
-        // doi (?)
-        r"[-a-zA-Z0-9@:%._\+~#=]{1,256}\.[a-zA-Z0-9()]{1,6}\b([-a-zA-Z0-9()@:%_\+.~#?&//=]*)",
-        // code blocks
-        r"```[a-z]*\n[\s\S]*?\n```",
-        // yaml blocks
-        r"---[a-z]*\n[\s\S]*?\n---",
-    ];
-
-    let mut output = String::from(text).to_lowercase();
-
-    for reg in &regex_strings {
-        output = match Regex::new(reg) {
-            Ok(res) => res.replace_all(&output[..], " ").to_string(),
-            Err(_) => output,
-        };
+/// Split text into words at whitespace.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Whitespace;
+
+impl Tokenizer for Whitespace {
+    fn tokenise(&self, text: &str) -> Vec<Token> {
+        text.split_whitespace()
+            .zip(0..)
+            .map(|(word, position)| Token {
+                text: String::from(word),
+                position,
+                span: span_in(text, word),
+            })
+            .collect()
     }
 
-    output
+    fn config(&self) -> String {
+        String::from("whitespace")
+    }
+}
+
+/// Split text into words at the word boundaries of Unicode Standard Annex #29, leaving out the
+/// spaces and punctuation between them, so that text in scripts written without spaces between
+/// words is split too.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct UnicodeWords;
+
+impl Tokenizer for UnicodeWords {
+    fn tokenise(&self, text: &str) -> Vec<Token> {
+        text.unicode_word_indices()
+            .zip(0..)
+            .map(|((start, word), position)| Token {
+                text: String::from(word),
+                position,
+                span: start..start + word.len(),
+            })
+            .collect()
+    }
+
+    fn config(&self) -> String {
+        String::from("unicode")
+    }
+}
+
+/// Patterns for words which are kept whole although they have punctuation in them: hyphenated
+/// words and identifiers (`iso-8601`), version numbers (`v1.5.5`) and names like `c++` and `c#`.
+const PROTECTED_PATTERNS: [&str; 3] = [
+    r"\b[\p{L}\p{N}]+(?:-[\p{L}\p{N}]+)+\b",
+    r"\b[vV]?\p{N}+(?:\.\p{N}+)+\b",
+    r"\b\p{L}[\p{L}\p{N}]*(?:\+\+|#)",
+];
+
+/// Split text into words like `UnicodeWords`, but keep words matching protected patterns (see
+/// `PROTECTED_PATTERNS`, and any others given) whole.
+///
+/// A word made of several runs of letters and digits (`http2`, `e0502`, `v1.5.5`, `snake_case`)
+/// is followed by each run as a word of its own, the first at the same position as the whole
+/// word, so that `iso-8601` can be found by `iso`, `8601` or `"iso 8601"`.
+#[derive(Debug, Clone)]
+pub struct TechnicalWords {
+    /// Patterns given as well as the built in ones.
+    extra: Vec<Regex>,
+    patterns: Vec<Regex>,
+}
+
+impl Default for TechnicalWords {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl TechnicalWords {
+    /// A tokenizer which also keeps words matching `extra` whole. The patterns are matched
+    /// against the text as it reaches the tokenizer, which the default clean up leaves in its
+    /// original case.
+    pub fn new(extra: Vec<Regex>) -> Self {
+        let mut patterns: Vec<Regex> = PROTECTED_PATTERNS
+            .iter()
+            .filter_map(|pattern| Regex::new(pattern).ok())
+            .collect();
+        patterns.extend(extra.iter().cloned());
+
+        Self { extra, patterns }
+    }
+}
+
+impl Tokenizer for TechnicalWords {
+    fn tokenise(&self, text: &str) -> Vec<Token> {
+        // The byte ranges of protected words, longest first where they start together.
+        let mut protected: Vec<(usize, usize)> = self
+            .patterns
+            .iter()
+            .flat_map(|pattern| pattern.find_iter(text).map(|m| (m.start(), m.end())))
+            .collect();
+        protected.sort_unstable_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+
+        let mut words: Vec<&str> = Vec::new();
+        let mut next = 0;
+        for (start, end) in protected {
+            if start < next {
+                continue;
+            }
+            words.extend(text[next..start].unicode_words());
+            words.push(&text[start..end]);
+            next = end;
+        }
+        words.extend(text[next..].unicode_words());
+
+        let mut tokens: Vec<Token> = Vec::with_capacity(words.len());
+        let mut position = 0;
+        for word in words {
+            tokens.push(Token {
+                text: String::from(word),
+                position,
+                span: span_in(text, word),
+            });
+
+            let parts = word_parts(word);
+            if parts.len() > 1 {
+                tokens.extend(
+                    parts
+                        .into_iter()
+                        .zip(position..)
+                        .map(|(part, position)| Token {
+                            text: String::from(part),
+                            position,
+                            span: span_in(text, part),
+                        }),
+                );
+            }
+            position = tokens.last().map_or(0, |token| token.position + 1);
+        }
+        tokens
+    }
+
+    fn config(&self) -> String {
+        let mut config = String::from("technical");
+        for pattern in &self.extra {
+            config.push(' ');
+            config.push_str(pattern.as_str());
+        }
+        config
+    }
 }
 
-///  Get a vector of all of the words in a corpus.
-pub fn get_all_words(corpus: &[Vec<String>]) -> HashMap<String, i32> {
-    let mut all_words: HashMap<String, i32> = HashMap::new();
+/// The runs of letters and of digits in a word, e.g. `v`, `1`, `5` and `5` in `v1.5.5`.
+fn word_parts(word: &str) -> Vec<&str> {
+    let mut parts: Vec<&str> = Vec::new();
+    // The start of the current run, and whether it is of digits.
+    let mut run: Option<(usize, bool)> = None;
+
+    for (i, c) in word.char_indices() {
+        let digit: Option<bool> = if c.is_numeric() {
+            Some(true)
+        } else if c.is_alphabetic() || is_combining_mark(c) {
+            Some(false)
+        } else {
+            None
+        };
+
+        match run {
+            Some((_, run_digit)) if digit == Some(run_digit) => {}
+            Some((start, _)) => {
+                parts.push(&word[start..i]);
+                run = digit.map(|digit| (i, digit));
+            }
+            None => run = digit.map(|digit| (i, digit)),
+        }
+    }
+    if let Some((start, _)) = run {
+        parts.push(&word[start..]);
+    }
+
+    parts
+}
 
-    corpus
+/// Number a list of words which are already free of stop words as consecutive tokens, with
+/// empty spans as they were not read from a text.
+pub fn tokens_from_words(words: &[String]) -> Vec<Token> {
+    words
         .iter()
-        .flatten()
-        .map(|word| {
-            let counter = all_words.entry(word.to_string()).or_insert(0);
-            *counter += 1;
+        .zip(0..)
+        .map(|(word, position)| Token {
+            text: word.clone(),
+            position,
+            span: 0..0,
         })
-        .for_each(drop);
+        .collect()
+}
+
+/// Clean up the text in a string, removing certain 'code' elements.
+///
+/// Code blocks, YAML front matter, math, web links and DOIs are removed, the text is put in Unicode
+/// normal form C (so that an accented letter is the same whether or not it was typed as a letter
+/// and a combining accent) and lowercased, and everything but letters and digits, in any script,
+/// and the punctuation of technical words (`v1.5`, `iso-8601`, `c++`, `c#`, `snake_case`) is
+/// removed. Files are split by `split_markdown` before this, which handles markdown more fully.
+pub fn clean_up_text(text: &str) -> String {
+    clean_up_text_keeping_case(text).to_lowercase()
+}
+
+/// The patterns removed by `clean_up_text_keeping_case`, compiled once. The patterns for whole
+/// elements come before those removing characters they need.
+static CLEAN_UP_PATTERNS: LazyLock<Vec<Regex>> = LazyLock::new(|| {
+    [
+        r"```[^\n]*\n[\s\S]*?\n```",              // code blocks
+        r"\A---\r?\n[\s\S]*?\n(---|\.\.\.)\r?\n", // yaml front matter
+        r"\$\$[\s\S]*?\$\$",                      // display math
+        r"\$[^\s$]([^$\n]*[^\s$\\])?\$",          // inline math
+        // web links
+        r"https?://(www\.)?[-a-zA-Z0-9@:%._\+~#=]{1,256}\.[a-zA-Z0-9()]{1,6}\b([-a-zA-Z0-9()@:%_\+.~#?&//=]*)",
+        // doi
+        r"\b10\.\d{4,9}/\S+",
+        r"[^\p{L}\p{M}\p{N} _.+#-]", // anything but letters, digits and some punctuation
+        r"_+([^\p{L}\p{M}\p{N}_]|$)", // underscores ending a word, e.g. of markdown emphasis
+        r"(^|[^\p{L}\p{M}\p{N}_])_+", // and starting one
+        " +",                        // more than one space
+    ]
+    .iter()
+    .filter_map(|pattern| Regex::new(pattern).ok())
+    .collect()
+});
+
+/// Clean up text like `clean_up_text`, but without lowercasing it, so that identifiers such as
+/// `loadCorpus` can still be split into words (see `IdentifierParts`).
+pub fn clean_up_text_keeping_case(text: &str) -> String {
+    let mut output = text.nfc().collect::<String>();
 
-    all_words
+    for pattern in CLEAN_UP_PATTERNS.iter() {
+        output = pattern.replace_all(&output, " ").to_string();
+    }
+
+    output
 }
 
 #[cfg(test)]
@@ -82,6 +309,22 @@ mod tests {
         assert_eq!(Err("No words found.".to_string()), result_2);
     }
 
+    #[test]
+    fn test_tokenise_positions() {
+        let text: String = "the sky is blue and the sun is bright".to_string();
+
+        let result = tokenise_positions(&text).unwrap();
+
+        let expected: Vec<(&str, u32)> = vec![("sky", 1), ("blue", 3), ("sun", 6), ("bright", 8)];
+        assert_eq!(
+            expected,
+            result
+                .iter()
+                .map(|token| (token.text.as_str(), token.position))
+                .collect::<Vec<(&str, u32)>>()
+        );
+    }
+
     #[test]
     fn test_clean_up_text() {
         let text: String = "this is some text".to_string();
@@ -92,35 +335,84 @@ mod tests {
     }
 
     #[test]
-    fn test_get_all_words() {
-        let corpus: Vec<Vec<String>> = vec![
-            vec![
-                "this".to_string(),
-                "is".to_string(),
-                "some".to_string(),
-                "text".to_string(),
-            ],
-            vec![
-                "and".to_string(),
-                "there".to_string(),
-                "is".to_string(),
-                "more".to_string(),
-            ],
-            vec!["this".to_string(), "too".to_string()],
+    fn test_clean_up_text_code() {
+        let text =
+            "---\ntitle: notes\n---\nRead https://example.com/a_b?c=d (doi 10.1000/xyz123)\n\
+            ```rust\nlet x = vec![1];\n```\nwhere $x^2$ costs $5.\n";
+
+        let result = clean_up_text(text);
+
+        assert_eq!("read doi where costs 5.", result.trim());
+    }
+
+    #[test]
+    fn test_clean_up_text_unicode() {
+        // "Café" with a combining accent, Greek, Cyrillic and digits.
+        let text: String = "Cafe\u{301}, Καλημέρα! Привет; 1984".to_string();
+
+        let result = clean_up_text(&text);
+
+        assert_eq!("café καλημέρα привет 1984".to_string(), result);
+    }
+
+    #[test]
+    fn test_technical_words() {
+        let text = clean_up_text("Use HTTP2 (v1.5.5) with ISO-8601 dates, C++ or C#: E0502!");
+
+        let result: Vec<(String, u32)> = TechnicalWords::default()
+            .tokenise(&text)
+            .into_iter()
+            .map(|token| (token.text, token.position))
+            .collect();
+
+        let expected: Vec<(&str, u32)> = vec![
+            ("use", 0),
+            ("http2", 1),
+            ("http", 1),
+            ("2", 2),
+            ("v1.5.5", 3),
+            ("v", 3),
+            ("1", 4),
+            ("5", 5),
+            ("5", 6),
+            ("with", 7),
+            ("iso-8601", 8),
+            ("iso", 8),
+            ("8601", 9),
+            ("dates", 10),
+            ("c++", 11),
+            ("or", 12),
+            ("c#", 13),
+            ("e0502", 14),
+            ("e", 14),
+            ("0502", 15),
         ];
+        assert_eq!(
+            expected,
+            result
+                .iter()
+                .map(|(text, position)| (text.as_str(), *position))
+                .collect::<Vec<(&str, u32)>>()
+        );
 
-        let mut words: HashMap<String, i32> = HashMap::new();
-        words.insert("this".to_string(), 2);
-        words.insert("is".to_string(), 2);
-        words.insert("some".to_string(), 1);
-        words.insert("text".to_string(), 1);
-        words.insert("and".to_string(), 1);
-        words.insert("there".to_string(), 1);
-        words.insert("more".to_string(), 1);
-        words.insert("too".to_string(), 1);
+        // More patterns for words to keep whole.
+        let tokenizer = TechnicalWords::new(vec![Regex::new(r"[a-z]+/[a-z]+").unwrap()]);
+        let result: Vec<String> = tokenizer
+            .tokenise("see tcp/ip")
+            .into_iter()
+            .map(|token| token.text)
+            .collect();
+        assert_eq!(vec!["see", "tcp/ip", "tcp", "ip"], result);
+    }
 
-        let result: HashMap<String, i32> = get_all_words(&corpus);
+    #[test]
+    fn test_unicode_words() {
+        let result: Vec<String> = UnicodeWords
+            .tokenise("naïve café, 東京")
+            .into_iter()
+            .map(|token| token.text)
+            .collect();
 
-        assert_eq!(words, result);
+        assert_eq!(vec!["naïve", "café", "東", "京"], result);
     }
 }
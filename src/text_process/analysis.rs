@@ -0,0 +1,442 @@
+use super::actions::{TechnicalWords, Token, UnicodeWords, Whitespace};
+use super::filters::{
+    part_span, AsciiFolding, CleanUp, IdentifierParts, LengthLimit, Lowercase, StopWords, Strip,
+    Synonyms,
+};
+use super::stemming::{Language, Stem};
+use regex::Regex;
+use std::fs;
+
+/// Changes text before it is split into tokens.
+pub trait CharFilter: Send + Sync {
+    fn filter(&self, text: &str) -> String;
+
+    /// How the filter is written in an analyzer config, e.g. `clean_up`.
+    fn config(&self) -> String;
+}
+
+/// Splits text into tokens, numbering them by position and noting the bytes each was read from.
+pub trait Tokenizer: Send + Sync {
+    fn tokenise(&self, text: &str) -> Vec<Token>;
+
+    /// How the tokenizer is written in an analyzer config, e.g. `whitespace`.
+    fn config(&self) -> String;
+}
+
+/// Changes, removes or adds tokens. Tokens added at the same position as another (e.g. synonyms)
+/// match wherever it does.
+pub trait TokenFilter: Send + Sync {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token>;
+
+    /// How the filter is written in an analyzer config, e.g. `stem english`.
+    fn config(&self) -> String;
+}
+
+/// Turns the text of documents and queries into tokens: the text is passed through each character
+/// filter in turn, split by the tokenizer, and the tokens passed through each token filter in
+/// turn.
+///
+/// The same analyzer must be used for a model and the queries against it, so a model keeps the
+/// analyzer it was built with, and an index stores it in its config form (see
+/// `Analyzer::from_config`).
+pub struct Analyzer {
+    char_filters: Vec<Box<dyn CharFilter>>,
+    tokenizer: Box<dyn Tokenizer>,
+    token_filters: Vec<Box<dyn TokenFilter>>,
+}
+
+impl Default for Analyzer {
+    /// The standard analysis: clean up the text (see `clean_up_text`), split it into words (see
+    /// `TechnicalWords`), lowercase them and remove stop words.
+    fn default() -> Self {
+        Analyzer::new(Box::new(TechnicalWords::default()))
+            .with_char_filter(Box::new(CleanUp))
+            .with_token_filter(Box::new(Lowercase))
+            .with_token_filter(Box::new(StopWords::default()))
+    }
+}
+
+impl PartialEq for Analyzer {
+    fn eq(&self, other: &Self) -> bool {
+        self.config() == other.config()
+    }
+}
+
+impl std::fmt::Debug for Analyzer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Analyzer").field(&self.config()).finish()
+    }
+}
+
+impl Analyzer {
+    /// An analyzer which only splits text with a tokenizer, to add filters to.
+    pub fn new(tokenizer: Box<dyn Tokenizer>) -> Self {
+        Self {
+            char_filters: Vec::new(),
+            tokenizer,
+            token_filters: Vec::new(),
+        }
+    }
+
+    pub fn with_char_filter(mut self, filter: Box<dyn CharFilter>) -> Self {
+        self.char_filters.push(filter);
+        self
+    }
+
+    pub fn with_token_filter(mut self, filter: Box<dyn TokenFilter>) -> Self {
+        self.token_filters.push(filter);
+        self
+    }
+
+    /// Turn text into tokens.
+    pub fn analyse(&self, text: &str) -> Vec<Token> {
+        let mut text: String = text.to_string();
+        for filter in &self.char_filters {
+            text = filter.filter(&text);
+        }
+
+        self.filter_tokens(self.tokenizer.tokenise(&text))
+    }
+
+    /// Turn text into tokens as `analyse` does, but without the character filters, so that the
+    /// span of each token is a byte range of the text as it was given. Character filters may
+    /// remove or move text, so this is only close to `analyse`; it is meant for finding the
+    /// words of a file again (e.g. to highlight them), not for indexing.
+    pub fn analyse_source(&self, text: &str) -> Vec<Token> {
+        self.filter_tokens(self.tokenizer.tokenise(text))
+    }
+
+    fn filter_tokens(&self, mut tokens: Vec<Token>) -> Vec<Token> {
+        for filter in &self.token_filters {
+            tokens = filter.filter(tokens);
+        }
+        split_colons(tokens)
+    }
+
+    /// The analyzer written as a config, which `from_config` reads back.
+    pub fn config(&self) -> String {
+        let mut lines: Vec<String> = Vec::new();
+        lines.extend(
+            self.char_filters
+                .iter()
+                .map(|filter| format!("char_filter {}", filter.config())),
+        );
+        lines.push(format!("tokenizer {}", self.tokenizer.config()));
+        lines.extend(
+            self.token_filters
+                .iter()
+                .map(|filter| format!("filter {}", filter.config())),
+        );
+        lines.join("\n")
+    }
+
+    /// Build an analyzer from a config: one component per line, in the order they are applied,
+    /// with blank lines and lines starting with `#` ignored. For example:
+    ///
+    /// ```text
+    /// char_filter clean_up
+    /// char_filter strip <regex>
+    /// tokenizer technical [<regex> ...]
+    /// filter identifier_parts
+    /// filter lowercase
+    /// filter stop_words [<word> ...]
+    /// filter length <min> <max>
+    /// filter ascii_folding
+    /// filter synonyms car automobile, big large
+    /// filter stem english
+    /// ```
+    ///
+    /// The tokenizer is one of `whitespace`, `unicode` (see `UnicodeWords`) or `technical` (see
+    /// `TechnicalWords`), which takes patterns (without spaces) for more words to keep whole.
+    /// `stop_words` without any words uses the built in list, and each comma separated group of
+    /// `synonyms` is a set of words which match each other.
+    pub fn from_config(config: &str) -> Result<Self, String> {
+        let mut char_filters: Vec<Box<dyn CharFilter>> = Vec::new();
+        let mut tokenizer: Option<Box<dyn Tokenizer>> = None;
+        let mut token_filters: Vec<Box<dyn TokenFilter>> = Vec::new();
+
+        for (line, number) in config.lines().zip(1..) {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let at_line = |err: String| format!("Line {number} of the analyzer config: {err}");
+            let (kind, value) = line.split_once(' ').unwrap_or((line, ""));
+            let value = value.trim();
+            match kind {
+                "char_filter" => char_filters.push(char_filter(value).map_err(at_line)?),
+                "tokenizer" if tokenizer.is_some() => {
+                    return Err(at_line(String::from("there can only be one tokenizer.")))
+                }
+                "tokenizer" => tokenizer = Some(tokenizer_from(value).map_err(at_line)?),
+                "filter" => token_filters.push(token_filter(value).map_err(at_line)?),
+                _ => {
+                    return Err(at_line(format!(
+                        "expected char_filter, tokenizer or filter, not '{kind}'."
+                    )))
+                }
+            }
+        }
+
+        let Some(tokenizer) = tokenizer else {
+            return Err(String::from("The analyzer config has no tokenizer."));
+        };
+
+        Ok(Self {
+            char_filters,
+            tokenizer,
+            token_filters,
+        })
+    }
+
+    /// Read an analyzer config file (see `from_config`).
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let config = fs::read_to_string(path)
+            .map_err(|err| format!("Could not read analyzer config {path}: {err}"))?;
+        Self::from_config(&config)
+    }
+}
+
+fn char_filter(value: &str) -> Result<Box<dyn CharFilter>, String> {
+    let (name, args) = value.split_once(' ').unwrap_or((value, ""));
+    match name {
+        "clean_up" => Ok(Box::new(CleanUp)),
+        "strip" => Regex::new(args.trim())
+            .map(|pattern| Box::new(Strip { pattern }) as Box<dyn CharFilter>)
+            .map_err(|err| format!("invalid pattern for strip: {err}")),
+        _ => Err(format!("unknown char_filter '{name}'.")),
+    }
+}
+
+fn tokenizer_from(value: &str) -> Result<Box<dyn Tokenizer>, String> {
+    let (name, args) = value.split_once(' ').unwrap_or((value, ""));
+    match (name, args.trim()) {
+        ("whitespace", "") => Ok(Box::new(Whitespace)),
+        ("unicode", "") => Ok(Box::new(UnicodeWords)),
+        ("technical", patterns) => patterns
+            .split_whitespace()
+            .map(Regex::new)
+            .collect::<Result<Vec<Regex>, regex::Error>>()
+            .map(|patterns| Box::new(TechnicalWords::new(patterns)) as Box<dyn Tokenizer>)
+            .map_err(|err| format!("invalid pattern for technical: {err}")),
+        ("whitespace" | "unicode", _) => Err(format!("{name} does not take any arguments.")),
+        _ => Err(format!("unknown tokenizer '{name}'.")),
+    }
+}
+
+fn token_filter(value: &str) -> Result<Box<dyn TokenFilter>, String> {
+    let (name, args) = value.split_once(' ').unwrap_or((value, ""));
+    let args: Vec<&str> = args.split_whitespace().collect();
+    match (name, args.as_slice()) {
+        ("identifier_parts", []) => Ok(Box::new(IdentifierParts)),
+        ("lowercase", []) => Ok(Box::new(Lowercase)),
+        ("ascii_folding", []) => Ok(Box::new(AsciiFolding)),
+        ("stop_words", []) => Ok(Box::new(StopWords::default())),
+        ("stop_words", words) => Ok(Box::new(StopWords {
+            words: words.iter().map(|word| word.to_string()).collect(),
+        })),
+        ("length", [min, max]) => match (min.parse(), max.parse()) {
+            (Ok(min), Ok(max)) => Ok(Box::new(LengthLimit { min, max })),
+            _ => Err(format!("invalid lengths '{min} {max}'.")),
+        },
+        ("stem", [language]) => Language::from_name(language)
+            .map(|language| Box::new(Stem { language }) as Box<dyn TokenFilter>)
+            .ok_or_else(|| format!("unknown stemming language '{language}'.")),
+        ("synonyms", _) => {
+            let groups: Vec<Vec<String>> = value["synonyms".len()..]
+                .split(',')
+                .map(|group| group.split_whitespace().map(String::from).collect())
+                .filter(|group: &Vec<String>| group.len() > 1)
+                .collect();
+            Ok(Box::new(Synonyms::new(groups)))
+        }
+        ("identifier_parts" | "lowercase" | "ascii_folding" | "length" | "stem", _) => {
+            Err(format!("wrong number of arguments for {name}."))
+        }
+        _ => Err(format!("unknown filter '{name}'.")),
+    }
+}
+
+/// Split tokens at colons, which separate a field from its word in the vocabulary (see
+/// `Field::term`), so that no word of the body is taken for one of another field whatever the
+/// analyzer keeps. The parts take the positions of consecutive words.
+fn split_colons(tokens: Vec<Token>) -> Vec<Token> {
+    if !tokens.iter().any(|token| token.text.contains(':')) {
+        return tokens;
+    }
+
+    let mut split: Vec<Token> = Vec::with_capacity(tokens.len());
+    // How far the tokens have moved along for the parts added before them.
+    let mut shift = 0;
+    for token in tokens {
+        let position = token.position + shift;
+        if !token.text.contains(':') {
+            split.push(Token { position, ..token });
+            continue;
+        }
+
+        let parts: Vec<&str> = token
+            .text
+            .split(':')
+            .filter(|part| !part.is_empty())
+            .collect();
+        shift += (parts.len() as u32).saturating_sub(1);
+        split.extend(parts.iter().zip(position..).map(|(part, position)| Token {
+            text: part.to_string(),
+            position,
+            span: part_span(&token, part),
+        }));
+    }
+    split
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_analyzer() {
+        let result = Analyzer::default().analyse("The sky is BLUE!");
+
+        assert_eq!(
+            vec![("sky", 1), ("blue", 3)],
+            result
+                .iter()
+                .map(|token| (token.text.as_str(), token.position))
+                .collect::<Vec<(&str, u32)>>()
+        );
+        assert_eq!(
+            Analyzer::default().config(),
+            "char_filter clean_up\ntokenizer technical\nfilter lowercase\nfilter stop_words"
+        );
+    }
+
+    #[test]
+    fn test_identifier_parts() {
+        let analyzer = Analyzer::from_config(
+            "char_filter clean_up\ntokenizer technical\nfilter identifier_parts\n\
+            filter lowercase\nfilter stop_words",
+        )
+        .unwrap();
+
+        let result = analyzer.analyse("Calls loadCorpus_v2 and tf_idf, _not_ __init__.");
+
+        assert_eq!(
+            vec![
+                ("calls", 0),
+                ("loadcorpus_v2", 1),
+                ("loadcorpus", 1),
+                ("load", 1),
+                ("corpus", 2),
+                ("v", 3),
+                ("2", 4),
+                ("tf_idf", 6),
+                ("tf", 6),
+                ("idf", 7),
+                ("init", 9)
+            ],
+            result
+                .iter()
+                .map(|token| (token.text.as_str(), token.position))
+                .collect::<Vec<(&str, u32)>>()
+        );
+    }
+
+    #[test]
+    fn test_analyse_source() {
+        let analyzer = Analyzer::from_config(
+            "char_filter clean_up\ntokenizer technical\nfilter identifier_parts\n\
+            filter lowercase\nfilter stop_words",
+        )
+        .unwrap();
+        let text = "See **loadCorpus** in v1.5";
+
+        let result = analyzer.analyse_source(text);
+
+        assert_eq!(
+            vec![
+                ("see", "See"),
+                ("loadcorpus", "loadCorpus"),
+                ("load", "load"),
+                ("corpus", "Corpus"),
+                ("v1.5", "v1.5"),
+                ("v", "v"),
+                ("1", "1"),
+                ("5", "5")
+            ],
+            result
+                .iter()
+                .map(|token| (token.text.as_str(), &text[token.span.clone()]))
+                .collect::<Vec<(&str, &str)>>()
+        );
+    }
+
+    #[test]
+    fn test_split_colons() {
+        let analyzer = Analyzer::from_config("tokenizer whitespace").unwrap();
+        let text = "see title:foo :: std::vec::Vec";
+
+        let result = analyzer.analyse_source(text);
+
+        assert_eq!(
+            vec![
+                ("see", "see", 0),
+                ("title", "title", 1),
+                ("foo", "foo", 2),
+                ("std", "std", 4),
+                ("vec", "vec", 5),
+                ("Vec", "Vec", 6)
+            ],
+            result
+                .iter()
+                .map(|token| (
+                    token.text.as_str(),
+                    &text[token.span.clone()],
+                    token.position
+                ))
+                .collect::<Vec<(&str, &str, u32)>>()
+        );
+    }
+
+    #[test]
+    fn test_from_config() {
+        let config = "# Notes\nchar_filter strip [0-9]+\n\ntokenizer whitespace\n\
+            filter lowercase\nfilter ascii_folding\nfilter length 3 20\n\
+            filter synonyms car automobile\nfilter stem english";
+
+        let analyzer = Analyzer::from_config(config).unwrap();
+
+        let result: Vec<String> = analyzer
+            .analyse("Cafés 42 and a car")
+            .into_iter()
+            .map(|token| token.text)
+            .collect();
+        assert_eq!(vec!["cafe", "and", "car", "automobil"], result);
+
+        // The config is written without the comment and blank line, and reads back the same.
+        let written = analyzer.config();
+        assert_eq!(written.lines().count(), 7);
+        assert_eq!(Analyzer::from_config(&written).unwrap(), analyzer);
+    }
+
+    #[test]
+    fn test_from_config_errors() {
+        let result = Analyzer::from_config("tokenizer whitespace\nfilter stem klingon");
+        assert_eq!(
+            result.err(),
+            Some(String::from(
+                "Line 2 of the analyzer config: unknown stemming language 'klingon'."
+            ))
+        );
+
+        let result = Analyzer::from_config("filter lowercase");
+        assert_eq!(
+            result.err(),
+            Some(String::from("The analyzer config has no tokenizer."))
+        );
+
+        let result = Analyzer::from_config("tokenizer whitespace\nfilters lowercase");
+        assert!(result.err().unwrap().contains("not 'filters'"));
+    }
+}
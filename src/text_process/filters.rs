@@ -0,0 +1,384 @@
+use super::actions::{clean_up_text_keeping_case, Token};
+use super::analysis::{CharFilter, TokenFilter};
+use super::stop_words::STOP_WORDS;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// The standard clean up of `clean_up_text_keeping_case`: remove everything but letters, digits
+/// and the punctuation of technical words, leaving lowercasing to the `Lowercase` token filter.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CleanUp;
+
+impl CharFilter for CleanUp {
+    fn filter(&self, text: &str) -> String {
+        clean_up_text_keeping_case(text)
+    }
+
+    fn config(&self) -> String {
+        String::from("clean_up")
+    }
+}
+
+/// Replace each match of a regular expression with a space.
+#[derive(Debug, Clone)]
+pub struct Strip {
+    pub pattern: Regex,
+}
+
+impl CharFilter for Strip {
+    fn filter(&self, text: &str) -> String {
+        self.pattern.replace_all(text, " ").to_string()
+    }
+
+    fn config(&self) -> String {
+        format!("strip {}", self.pattern.as_str())
+    }
+}
+
+/// Lowercase each token.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Lowercase;
+
+impl TokenFilter for Lowercase {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token> {
+        tokens
+            .into_iter()
+            .map(|token| Token {
+                text: token.text.to_lowercase(),
+                ..token
+            })
+            .collect()
+    }
+
+    fn config(&self) -> String {
+        String::from("lowercase")
+    }
+}
+
+/// Follow each identifier in camelCase, PascalCase or snake_case (`loadCorpus`, `HTTPServer`,
+/// `tf_idf_calculation`) by its words, the first at the same position as the identifier, so that
+/// a search for a word finds the identifiers containing it. It must come before `Lowercase`.
+///
+/// A token followed by another at the same position has been split already (e.g. by
+/// `TechnicalWords`) and is left alone, though its parts may be split in turn.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct IdentifierParts;
+
+impl IdentifierParts {
+    /// The words of an identifier: its parts between underscores, split again before a capital
+    /// which follows a lowercase letter or digit (`load|Corpus`), or which starts a word after a
+    /// run of capitals (`HTTP|Server`).
+    pub fn parts(word: &str) -> Vec<&str> {
+        let mut parts: Vec<&str> = Vec::new();
+        for piece in word.split('_').filter(|piece| !piece.is_empty()) {
+            let chars: Vec<(usize, char)> = piece.char_indices().collect();
+            let mut start = 0;
+            for (i, &(at, c)) in chars.iter().enumerate().skip(1) {
+                let previous = chars[i - 1].1;
+                let next_lowercase = chars
+                    .get(i + 1)
+                    .is_some_and(|&(_, next)| next.is_lowercase());
+                if c.is_uppercase()
+                    && (previous.is_lowercase()
+                        || previous.is_numeric()
+                        || (previous.is_uppercase() && next_lowercase))
+                {
+                    parts.push(&piece[start..at]);
+                    start = at;
+                }
+            }
+            parts.push(&piece[start..]);
+        }
+        parts
+    }
+}
+
+impl TokenFilter for IdentifierParts {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token> {
+        let mut split: Vec<Token> = Vec::with_capacity(tokens.len());
+        // How far the tokens have moved along for the words added before them.
+        let mut shift = 0;
+        for (i, token) in tokens.iter().enumerate() {
+            let position = token.position + shift;
+            split.push(Token {
+                position,
+                ..token.clone()
+            });
+
+            if tokens
+                .get(i + 1)
+                .is_some_and(|next| next.position == token.position)
+            {
+                continue;
+            }
+            let parts = Self::parts(&token.text);
+            if parts.len() > 1 {
+                shift += parts.len() as u32 - 1;
+                split.extend(
+                    parts
+                        .into_iter()
+                        .zip(position..)
+                        .map(|(part, position)| Token {
+                            text: String::from(part),
+                            position,
+                            span: part_span(token, part),
+                        }),
+                );
+            }
+        }
+        split
+    }
+
+    fn config(&self) -> String {
+        String::from("identifier_parts")
+    }
+}
+
+/// The span of a part of a token's text: its own bytes if the token is still as it was read,
+/// otherwise the span of the whole token.
+pub(super) fn part_span(token: &Token, part: &str) -> Range<usize> {
+    if token.text.len() != token.span.len() {
+        return token.span.clone();
+    }
+    let start = token.span.start + (part.as_ptr() as usize - token.text.as_ptr() as usize);
+    start..start + part.len()
+}
+
+/// Remove common words which say little about a document. Their positions are left empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StopWords {
+    pub words: HashSet<String>,
+}
+
+impl Default for StopWords {
+    /// The built in list of English stop words.
+    fn default() -> Self {
+        Self {
+            words: STOP_WORDS.iter().map(|word| word.to_string()).collect(),
+        }
+    }
+}
+
+impl TokenFilter for StopWords {
+    fn filter(&self, mut tokens: Vec<Token>) -> Vec<Token> {
+        tokens.retain(|token| !self.words.contains(&token.text));
+        tokens
+    }
+
+    fn config(&self) -> String {
+        if *self == Self::default() {
+            return String::from("stop_words");
+        }
+
+        let mut words: Vec<&str> = self.words.iter().map(String::as_str).collect();
+        words.sort_unstable();
+        format!("stop_words {}", words.join(" "))
+    }
+}
+
+/// Remove tokens shorter than `min` or longer than `max` characters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LengthLimit {
+    pub min: usize,
+    pub max: usize,
+}
+
+impl TokenFilter for LengthLimit {
+    fn filter(&self, mut tokens: Vec<Token>) -> Vec<Token> {
+        tokens.retain(|token| (self.min..=self.max).contains(&token.text.chars().count()));
+        tokens
+    }
+
+    fn config(&self) -> String {
+        format!("length {} {}", self.min, self.max)
+    }
+}
+
+/// Replace accented letters with the letters without their accents (e.g. "café" with "cafe"),
+/// and a few letters with no decomposition with their usual ASCII spelling (e.g. "ß" with "ss").
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AsciiFolding;
+
+impl AsciiFolding {
+    pub fn fold(word: &str) -> String {
+        let mut folded = String::with_capacity(word.len());
+        for c in word.nfd().filter(|&c| !is_combining_mark(c)) {
+            match c {
+                'ß' => folded.push_str("ss"),
+                'æ' => folded.push_str("ae"),
+                'Æ' => folded.push_str("AE"),
+                'œ' => folded.push_str("oe"),
+                'Œ' => folded.push_str("OE"),
+                'þ' => folded.push_str("th"),
+                'Þ' => folded.push_str("TH"),
+                'ø' => folded.push('o'),
+                'Ø' => folded.push('O'),
+                'ł' => folded.push('l'),
+                'Ł' => folded.push('L'),
+                'đ' | 'ð' => folded.push('d'),
+                'Đ' | 'Ð' => folded.push('D'),
+                c => folded.push(c),
+            }
+        }
+        folded
+    }
+}
+
+impl TokenFilter for AsciiFolding {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token> {
+        tokens
+            .into_iter()
+            .map(|token| Token {
+                text: Self::fold(&token.text),
+                ..token
+            })
+            .collect()
+    }
+
+    fn config(&self) -> String {
+        String::from("ascii_folding")
+    }
+}
+
+/// Add the other words of a group of synonyms after any word of the group, at the same position,
+/// so that each word of the group matches the others.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Synonyms {
+    groups: Vec<Vec<String>>,
+    /// The group each word belongs to.
+    lookup: HashMap<String, usize>,
+}
+
+impl Synonyms {
+    pub fn new(groups: Vec<Vec<String>>) -> Self {
+        let mut lookup: HashMap<String, usize> = HashMap::new();
+        for (i, group) in groups.iter().enumerate() {
+            for word in group {
+                lookup.entry(word.clone()).or_insert(i);
+            }
+        }
+
+        Self { groups, lookup }
+    }
+}
+
+impl TokenFilter for Synonyms {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token> {
+        let mut expanded: Vec<Token> = Vec::with_capacity(tokens.len());
+        for token in tokens {
+            let Some(&group) = self.lookup.get(&token.text) else {
+                expanded.push(token);
+                continue;
+            };
+
+            let position = token.position;
+            let synonyms = self.groups[group]
+                .iter()
+                .filter(|&word| *word != token.text)
+                .map(|word| Token {
+                    text: word.clone(),
+                    position,
+                    span: token.span.clone(),
+                })
+                .collect::<Vec<Token>>();
+            expanded.push(token);
+            expanded.extend(synonyms);
+        }
+        expanded
+    }
+
+    fn config(&self) -> String {
+        let groups: Vec<String> = self.groups.iter().map(|group| group.join(" ")).collect();
+        format!("synonyms {}", groups.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text_process::tokens_from_words;
+
+    fn words(tokens: &[Token]) -> Vec<(&str, u32)> {
+        tokens
+            .iter()
+            .map(|token| (token.text.as_str(), token.position))
+            .collect()
+    }
+
+    fn tokens(text: &str) -> Vec<Token> {
+        let words: Vec<String> = text.split(' ').map(String::from).collect();
+        tokens_from_words(&words)
+    }
+
+    #[test]
+    fn test_token_filters() {
+        let result = Lowercase.filter(tokens("The Sky"));
+        assert_eq!(words(&result), vec![("the", 0), ("sky", 1)]);
+
+        let result = StopWords::default().filter(tokens("the sky is blue"));
+        assert_eq!(words(&result), vec![("sky", 1), ("blue", 3)]);
+
+        let result = LengthLimit { min: 2, max: 4 }.filter(tokens("a sky cloudy blue"));
+        assert_eq!(words(&result), vec![("sky", 1), ("blue", 3)]);
+
+        let result = AsciiFolding.filter(tokens("café Straße Ørsted"));
+        assert_eq!(
+            words(&result),
+            vec![("cafe", 0), ("Strasse", 1), ("Orsted", 2)]
+        );
+    }
+
+    #[test]
+    fn test_identifier_parts() {
+        let result =
+            IdentifierParts.filter(tokens("call loadCorpus on tf_idf_calculation HTTPServer"));
+
+        assert_eq!(
+            words(&result),
+            vec![
+                ("call", 0),
+                ("loadCorpus", 1),
+                ("load", 1),
+                ("Corpus", 2),
+                ("on", 3),
+                ("tf_idf_calculation", 4),
+                ("tf", 4),
+                ("idf", 5),
+                ("calculation", 6),
+                ("HTTPServer", 7),
+                ("HTTP", 7),
+                ("Server", 8)
+            ]
+        );
+        assert_eq!(
+            IdentifierParts::parts("utf8Decoder"),
+            vec!["utf8", "Decoder"]
+        );
+        assert_eq!(IdentifierParts::parts("__init__"), vec!["init"]);
+    }
+
+    #[test]
+    fn test_synonyms() {
+        let synonyms = Synonyms::new(vec![
+            vec!["car".to_string(), "automobile".to_string()],
+            vec!["big".to_string(), "large".to_string()],
+        ]);
+
+        let result = synonyms.filter(tokens("large car park"));
+
+        assert_eq!(
+            words(&result),
+            vec![
+                ("large", 0),
+                ("big", 0),
+                ("car", 1),
+                ("automobile", 1),
+                ("park", 2)
+            ]
+        );
+        assert_eq!(synonyms.config(), "synonyms car automobile, big large");
+    }
+}
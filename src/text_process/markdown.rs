@@ -0,0 +1,254 @@
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// The text of a markdown file, split into its prose and its code.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Markdown {
+    /// The text outside code, without front matter, URLs or math.
+    pub prose: String,
+    /// The contents of fenced code blocks and inline code spans, one per entry.
+    pub code: Vec<String>,
+}
+
+/// The patterns for the URLs of links, compiled once, with what each is replaced by.
+static LINKS: LazyLock<Vec<(Regex, &str)>> = LazyLock::new(|| {
+    [
+        // the destination of a link or image, e.g. [text](https://example.com "title")
+        (r"\]\([^)\s]*(\s+[^)]*)?\)", "]"),
+        // bare URLs and autolinks
+        (r"<?\b(https?|ftp)://[^\s<>]+>?", " "),
+        (r"\bwww\.[^\s<>]+", " "),
+    ]
+    .into_iter()
+    .filter_map(|(pattern, replacement)| Some((Regex::new(pattern).ok()?, replacement)))
+    .collect()
+});
+
+/// Split markdown into prose and code, leaving out the parts which are not text to search: the
+/// YAML or TOML front matter (whose title and tags are fields of their own, see `fields`), URLs
+/// (the text of a link is kept) and `$...$` or `$$...$$` math.
+///
+/// Code blocks are fenced with three or more backticks or tildes, and run to the end of the file
+/// if they are not closed. A dollar sign starts inline math only if it is followed by a non-space
+/// and closed by one which follows a non-space and is not followed by a digit, so that prices such
+/// as "$5 and $10" are left alone.
+pub fn split_markdown(contents: &str) -> Markdown {
+    let mut markdown = Markdown::default();
+    let mut paragraph: Vec<&str> = Vec::new();
+    // The character and length of the fence of the code block we are in, and its lines.
+    let mut fence: Option<(char, usize)> = None;
+    let mut block: Vec<&str> = Vec::new();
+
+    for line in skip_front_matter(contents).lines() {
+        let trimmed = line.trim();
+
+        if let Some((marker, length)) = fence {
+            if fence_length(trimmed, marker) >= length && trimmed.trim_matches(marker).is_empty() {
+                markdown.code.push(block.join("\n"));
+                block.clear();
+                fence = None;
+            } else {
+                block.push(line);
+            }
+            continue;
+        }
+
+        let opening = ['`', '~']
+            .into_iter()
+            .map(|marker| (marker, fence_length(trimmed, marker)))
+            .find(|&(_, length)| length >= 3);
+        if opening.is_some() || trimmed.is_empty() {
+            split_paragraph(&paragraph.join("\n"), &LINKS, &mut markdown);
+            paragraph.clear();
+            fence = opening;
+        } else {
+            paragraph.push(line);
+        }
+    }
+    split_paragraph(&paragraph.join("\n"), &LINKS, &mut markdown);
+    if fence.is_some() {
+        markdown.code.push(block.join("\n"));
+    }
+
+    markdown.code.retain(|code| !code.trim().is_empty());
+    markdown
+}
+
+/// The contents of a file after its front matter: a YAML block between `---` lines at the start
+/// of the file (closed by `---` or `...`), or a TOML block between `+++` lines.
+fn skip_front_matter(contents: &str) -> &str {
+    for (opening, closings) in [("---", &["---", "..."][..]), ("+++", &["+++"])] {
+        let Some(rest) = contents.strip_prefix(opening).and_then(|rest| {
+            rest.strip_prefix('\n')
+                .or_else(|| rest.strip_prefix("\r\n"))
+        }) else {
+            continue;
+        };
+
+        let mut offset = 0;
+        for line in rest.split_inclusive('\n') {
+            offset += line.len();
+            if closings.contains(&line.trim_end()) {
+                return &rest[offset..];
+            }
+        }
+    }
+    contents
+}
+
+/// The number of `marker` characters a line starts with.
+fn fence_length(line: &str, marker: char) -> usize {
+    line.len() - line.trim_start_matches(marker).len()
+}
+
+/// The byte offset of the first run of exactly `length` `marker` characters in `text`.
+fn find_run(text: &str, marker: char, length: usize) -> Option<usize> {
+    let mut offset = 0;
+    while let Some(start) = text[offset..].find(marker) {
+        let start = offset + start;
+        let run = fence_length(&text[start..], marker);
+        if run == length {
+            return Some(start);
+        }
+        offset = start + run;
+    }
+    None
+}
+
+/// The byte offset of the `$` closing inline math in `text`, which follows the opening `$`.
+fn closing_dollar(text: &str) -> Option<usize> {
+    if text.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let mut offset = 0;
+    while let Some(end) = find_run(&text[offset..], '$', 1) {
+        let end = offset + end;
+        let after_space = text[..end].ends_with(|c: char| c.is_whitespace() || c == '\\');
+        let before_digit = text[end + 1..].starts_with(|c: char| c.is_ascii_digit());
+        if end > 0 && !after_space && !before_digit {
+            return Some(end);
+        }
+        offset = end + 1;
+    }
+    None
+}
+
+/// Add a paragraph to the prose, moving its code spans to the code and leaving out its math and
+/// URLs.
+fn split_paragraph(text: &str, links: &[(Regex, &str)], markdown: &mut Markdown) {
+    let mut prose = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find(['`', '$']) {
+        let (before, from) = rest.split_at(start);
+        prose.push_str(before);
+
+        let marker = if from.starts_with('`') { '`' } else { '$' };
+        let run = fence_length(from, marker);
+        let escaped = before.ends_with('\\');
+
+        // Where the span (code or math) ends, and how long its closing delimiter is.
+        let end: Option<(usize, usize)> = match marker {
+            _ if escaped => None,
+            '`' => find_run(&from[run..], '`', run).map(|end| (end, run)),
+            _ if run >= 2 => find_run(&from[run..], '$', 2).map(|end| (end, 2)),
+            _ => closing_dollar(&from[1..]).map(|end| (end, 1)),
+        };
+
+        match end {
+            Some((end, closing)) => {
+                let span_start = if marker == '$' && run >= 2 { 2 } else { run };
+                if marker == '`' {
+                    markdown
+                        .code
+                        .push(from[span_start..span_start + end].trim().to_string());
+                }
+                prose.push(' ');
+                rest = &from[span_start + end + closing..];
+            }
+            None => {
+                prose.push_str(&from[..run]);
+                rest = &from[run..];
+            }
+        }
+    }
+    prose.push_str(rest);
+
+    for (link, replacement) in links {
+        prose = link.replace_all(&prose, *replacement).to_string();
+    }
+
+    if !prose.trim().is_empty() {
+        if !markdown.prose.is_empty() {
+            markdown.prose.push_str("\n\n");
+        }
+        markdown.prose.push_str(prose.trim());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_front_matter() {
+        let result = split_markdown("---\ntitle: Notes\ntags: [rust]\n---\nSome text.\n");
+        assert_eq!(result.prose, "Some text.");
+
+        // A horizontal rule at the start which is never closed is not front matter.
+        let result = split_markdown("---\nSome text.\n");
+        assert_eq!(result.prose, "---\nSome text.");
+
+        let result = split_markdown("+++\ntitle = \"Notes\"\n---\n+++\nSome text.\n");
+        assert_eq!(result.prose, "Some text.");
+    }
+
+    #[test]
+    fn test_fenced_code() {
+        let contents = "Before.\n\n```rust\nfn main() {}\n```\nBetween.\n~~~~\nlet x = 1;\n\
+            ```\nstill code\n~~~~\nAfter.\n```\nnever closed\n";
+
+        let result = split_markdown(contents);
+
+        assert_eq!(result.prose, "Before.\n\nBetween.\n\nAfter.");
+        assert_eq!(
+            result.code,
+            vec![
+                "fn main() {}",
+                "let x = 1;\n```\nstill code",
+                "never closed"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inline_code() {
+        let result = split_markdown("Call `loadCorpus` or ``a ` b``, not \\`this`.");
+
+        assert_eq!(result.prose, "Call   or  , not \\`this`.");
+        assert_eq!(result.code, vec!["loadCorpus", "a ` b"]);
+    }
+
+    #[test]
+    fn test_urls() {
+        let result = split_markdown(
+            "See [the docs](https://docs.rs/regex \"Docs\"), ![a chart](img/chart.png),\n\
+            <https://example.com/a?b=c> and www.example.org/page or http://x.io/y.",
+        );
+
+        assert_eq!(result.prose, "See [the docs], ![a chart],\n  and   or");
+    }
+
+    #[test]
+    fn test_math() {
+        let result = split_markdown(
+            "Energy $E = mc^2$ and\n$$\n\\sum_i x_i\n$$\nbut $5 and $10 are prices, as is \\$3.",
+        );
+
+        assert_eq!(
+            result.prose,
+            "Energy   and\n \nbut $5 and $10 are prices, as is \\$3."
+        );
+    }
+}
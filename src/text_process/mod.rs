@@ -1,8 +1,33 @@
 mod actions;
+mod analysis;
+mod filters;
+mod markdown;
+mod stemming;
 mod stop_words;
 
 pub use actions::clean_up_text;
-pub use actions::get_all_words;
+pub use actions::clean_up_text_keeping_case;
 pub use actions::tokenise;
-
-pub use stop_words::STOP_WORDS;
+pub use actions::tokenise_positions;
+pub use actions::tokens_from_words;
+pub use actions::TechnicalWords;
+pub use actions::Token;
+pub use actions::UnicodeWords;
+pub use actions::Whitespace;
+pub use analysis::Analyzer;
+pub use analysis::CharFilter;
+pub use analysis::TokenFilter;
+pub use analysis::Tokenizer;
+pub use filters::AsciiFolding;
+pub use filters::CleanUp;
+pub use filters::IdentifierParts;
+pub use filters::LengthLimit;
+pub use filters::Lowercase;
+pub use filters::StopWords;
+pub use filters::Strip;
+pub use filters::Synonyms;
+pub use markdown::split_markdown;
+pub use markdown::Markdown;
+pub use stemming::stemming_from_name;
+pub use stemming::Language;
+pub use stemming::Stem;
@@ -0,0 +1,179 @@
+use super::actions::Token;
+use super::analysis::TokenFilter;
+use rust_stemmers::{Algorithm, Stemmer};
+
+/// A language with a Snowball stemmer, which reduces words to a common stem (e.g. "borrowing"
+/// and "borrowed" to "borrow") so that they match each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    Arabic,
+    Danish,
+    Dutch,
+    English,
+    Finnish,
+    French,
+    German,
+    Greek,
+    Hungarian,
+    Italian,
+    Norwegian,
+    Portuguese,
+    Romanian,
+    Russian,
+    Spanish,
+    Swedish,
+    Tamil,
+    Turkish,
+}
+
+impl Language {
+    pub const ALL: [Language; 18] = [
+        Language::Arabic,
+        Language::Danish,
+        Language::Dutch,
+        Language::English,
+        Language::Finnish,
+        Language::French,
+        Language::German,
+        Language::Greek,
+        Language::Hungarian,
+        Language::Italian,
+        Language::Norwegian,
+        Language::Portuguese,
+        Language::Romanian,
+        Language::Russian,
+        Language::Spanish,
+        Language::Swedish,
+        Language::Tamil,
+        Language::Turkish,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::Arabic => "arabic",
+            Language::Danish => "danish",
+            Language::Dutch => "dutch",
+            Language::English => "english",
+            Language::Finnish => "finnish",
+            Language::French => "french",
+            Language::German => "german",
+            Language::Greek => "greek",
+            Language::Hungarian => "hungarian",
+            Language::Italian => "italian",
+            Language::Norwegian => "norwegian",
+            Language::Portuguese => "portuguese",
+            Language::Romanian => "romanian",
+            Language::Russian => "russian",
+            Language::Spanish => "spanish",
+            Language::Swedish => "swedish",
+            Language::Tamil => "tamil",
+            Language::Turkish => "turkish",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Language> {
+        Language::ALL
+            .into_iter()
+            .find(|language| language.name() == name)
+    }
+
+    fn algorithm(self) -> Algorithm {
+        match self {
+            Language::Arabic => Algorithm::Arabic,
+            Language::Danish => Algorithm::Danish,
+            Language::Dutch => Algorithm::Dutch,
+            Language::English => Algorithm::English,
+            Language::Finnish => Algorithm::Finnish,
+            Language::French => Algorithm::French,
+            Language::German => Algorithm::German,
+            Language::Greek => Algorithm::Greek,
+            Language::Hungarian => Algorithm::Hungarian,
+            Language::Italian => Algorithm::Italian,
+            Language::Norwegian => Algorithm::Norwegian,
+            Language::Portuguese => Algorithm::Portuguese,
+            Language::Romanian => Algorithm::Romanian,
+            Language::Russian => Algorithm::Russian,
+            Language::Spanish => Algorithm::Spanish,
+            Language::Swedish => Algorithm::Swedish,
+            Language::Tamil => Algorithm::Tamil,
+            Language::Turkish => Algorithm::Turkish,
+        }
+    }
+
+    /// The stem of a (lowercase) word.
+    pub fn stem(self, word: &str) -> String {
+        Stemmer::create(self.algorithm()).stem(word).into_owned()
+    }
+}
+
+/// Parse the name of a stemming setting: a language, or `none` for no stemming.
+pub fn stemming_from_name(name: &str) -> Result<Option<Language>, String> {
+    match name {
+        "none" => Ok(None),
+        name => Language::from_name(name).map(Some).ok_or_else(|| {
+            let names: Vec<&str> = Language::ALL
+                .iter()
+                .map(|language| language.name())
+                .collect();
+            format!(
+                "Unknown stemming language '{name}'; expected none or one of: {}.",
+                names.join(", ")
+            )
+        }),
+    }
+}
+
+/// Reduce each token to its stem.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stem {
+    pub language: Language,
+}
+
+impl TokenFilter for Stem {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token> {
+        let stemmer = Stemmer::create(self.language.algorithm());
+        tokens
+            .into_iter()
+            .map(|token| Token {
+                text: stemmer.stem(&token.text).into_owned(),
+                ..token
+            })
+            .collect()
+    }
+
+    fn config(&self) -> String {
+        format!("stem {}", self.language.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text_process::tokenise_positions;
+
+    #[test]
+    fn test_stem() {
+        let tokens = tokenise_positions("borrowing the borrowed books").unwrap();
+
+        let result = Stem {
+            language: Language::English,
+        }
+        .filter(tokens);
+
+        let stems: Vec<(&str, u32)> = result
+            .iter()
+            .map(|token| (token.text.as_str(), token.position))
+            .collect();
+        assert_eq!(vec![("borrow", 0), ("borrow", 2), ("book", 3)], stems);
+    }
+
+    #[test]
+    fn test_stemming_from_name() {
+        assert_eq!(stemming_from_name("english"), Ok(Some(Language::English)));
+        assert_eq!(stemming_from_name("none"), Ok(None));
+        assert!(stemming_from_name("klingon")
+            .unwrap_err()
+            .starts_with("Unknown stemming language 'klingon'"));
+        assert_eq!(Language::German.stem("häuser"), "haus");
+    }
+}
@@ -0,0 +1,301 @@
+use super::fuzzy::BkTree;
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::sync::OnceLock;
+
+/// Index of a term in a `Vocabulary`.
+pub type TermId = usize;
+
+/// The terms of a corpus, each with a stable id and its corpus statistics.
+///
+/// Ids are handed out in order of first occurrence, so the same documents loaded in the same
+/// order always produce the same ids. Terms are also kept in sorted order, for finding the terms
+/// which match a pattern, and in a BK-tree, for finding the terms similar to a word. The tree is
+/// only built on the first search for similar words, so that loading an index stays fast.
+#[derive(Debug, Default, Clone)]
+pub struct Vocabulary {
+    terms: Vec<String>,
+    ids: BTreeMap<String, TermId>,
+    similar: OnceLock<BkTree>,
+    collection_freq: Vec<u64>,
+    doc_freq: Vec<u32>,
+}
+
+impl PartialEq for Vocabulary {
+    /// Vocabularies are the same if they have the same terms and statistics, whether or not their
+    /// BK-trees have been built.
+    fn eq(&self, other: &Self) -> bool {
+        self.terms == other.terms
+            && self.collection_freq == other.collection_freq
+            && self.doc_freq == other.doc_freq
+    }
+}
+
+impl Vocabulary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build the vocabulary of a corpus, counting collection and document frequencies.
+    pub fn from_corpus<S: AsRef<str>>(corpus: &[Vec<S>]) -> Self {
+        let mut vocabulary = Self::new();
+
+        for doc in corpus {
+            vocabulary.add_document(doc);
+        }
+
+        vocabulary
+    }
+
+    /// Add the words of a document to the vocabulary and its statistics.
+    pub fn add_document<S: AsRef<str>>(&mut self, doc: &[S]) {
+        let mut seen: Vec<TermId> = Vec::new();
+
+        for word in doc {
+            let id = self.get_or_insert(word.as_ref());
+            self.collection_freq[id] += 1;
+            seen.push(id);
+        }
+
+        seen.sort_unstable();
+        seen.dedup();
+        for id in seen {
+            self.doc_freq[id] += 1;
+        }
+    }
+
+    /// Remove one document's occurrences of a term from the statistics. The term keeps its id.
+    pub fn remove_occurrences(&mut self, id: TermId, count: u64) {
+        self.collection_freq[id] -= count;
+        self.doc_freq[id] -= 1;
+    }
+
+    /// Get the id of a term, adding it (with zero counts) if it is new.
+    pub fn get_or_insert(&mut self, term: &str) -> TermId {
+        if let Some(&id) = self.ids.get(term) {
+            return id;
+        }
+
+        let id = self.terms.len();
+        self.terms.push(term.to_string());
+        self.ids.insert(term.to_string(), id);
+        if let Some(similar) = self.similar.get_mut() {
+            similar.insert(&self.terms, id);
+        }
+        self.collection_freq.push(0);
+        self.doc_freq.push(0);
+        id
+    }
+
+    /// Add a term along with its statistics, e.g. when reading a stored vocabulary.
+    pub fn insert_with_stats(&mut self, term: &str, collection_freq: u64, doc_freq: u32) -> TermId {
+        let id = self.get_or_insert(term);
+        self.collection_freq[id] = collection_freq;
+        self.doc_freq[id] = doc_freq;
+        id
+    }
+
+    /// Look up the id of a term.
+    pub fn id(&self, term: &str) -> Option<TermId> {
+        self.ids.get(term).copied()
+    }
+
+    /// Find the terms matching a pattern in which `*` stands for any run of characters and `?` for
+    /// any one character, in sorted order.
+    ///
+    /// Only the terms starting with the part of the pattern before the first wildcard are
+    /// visited, so patterns which start with a wildcard are the slowest.
+    pub fn matching_terms<'a>(
+        &'a self,
+        pattern: &str,
+    ) -> impl Iterator<Item = (&'a str, TermId)> + 'a {
+        let prefix: String = pattern
+            .find(['*', '?'])
+            .map_or(pattern, |end| &pattern[..end])
+            .to_string();
+        let pattern: Vec<char> = pattern.chars().collect();
+
+        self.ids
+            .range::<str, _>((Bound::Included(prefix.as_str()), Bound::Unbounded))
+            .take_while(move |(term, _)| term.starts_with(&prefix))
+            .filter(move |(term, _)| wildcard_match(&pattern, &term.chars().collect::<Vec<char>>()))
+            .map(|(term, &id)| (term.as_str(), id))
+    }
+
+    /// Find the terms within a Damerau-Levenshtein distance of a word, with their distances.
+    pub fn similar_terms(&self, word: &str, max_distance: usize) -> Vec<(TermId, usize)> {
+        let similar = self.similar.get_or_init(|| {
+            let mut similar = BkTree::new();
+            for id in 0..self.terms.len() {
+                similar.insert(&self.terms, id);
+            }
+            similar
+        });
+        similar.find(&self.terms, word, max_distance)
+    }
+
+    /// The term with a given id.
+    pub fn term(&self, id: TermId) -> &str {
+        &self.terms[id]
+    }
+
+    /// Total number of occurrences of a term across the corpus.
+    pub fn collection_freq(&self, id: TermId) -> u64 {
+        self.collection_freq[id]
+    }
+
+    /// Number of documents a term occurs in.
+    pub fn doc_freq(&self, id: TermId) -> u32 {
+        self.doc_freq[id]
+    }
+
+    pub fn len(&self) -> usize {
+        self.terms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+}
+
+/// Whether a text matches a pattern in which `*` stands for any run of characters and `?` for
+/// any one character.
+fn wildcard_match(pattern: &[char], text: &[char]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // Where to resume if the text so far does not match: after the last `*`, with that `*`
+    // taking one more character.
+    let mut resume: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                resume = Some((p + 1, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match resume {
+                Some((after_star, start)) => {
+                    p = after_star;
+                    t = start + 1;
+                    resume = Some((after_star, start + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_corpus() {
+        let corpus: Vec<Vec<String>> = vec![
+            vec![
+                "this".to_string(),
+                "is".to_string(),
+                "some".to_string(),
+                "text".to_string(),
+                "text".to_string(),
+            ],
+            vec![
+                "and".to_string(),
+                "there".to_string(),
+                "is".to_string(),
+                "more".to_string(),
+            ],
+            vec!["this".to_string(), "too".to_string()],
+        ];
+
+        let result = Vocabulary::from_corpus(&corpus);
+
+        assert_eq!(result.len(), 8);
+        assert_eq!(result.id("this"), Some(0));
+        assert_eq!(result.id("too"), Some(7));
+        assert_eq!(result.term(3), "text");
+        assert_eq!(result.id("absent"), None);
+
+        let text = result.id("text").unwrap();
+        assert_eq!(result.collection_freq(text), 2);
+        assert_eq!(result.doc_freq(text), 1);
+
+        let is = result.id("is").unwrap();
+        assert_eq!(result.collection_freq(is), 2);
+        assert_eq!(result.doc_freq(is), 2);
+    }
+
+    #[test]
+    fn test_matching_terms() {
+        let corpus: Vec<Vec<&str>> = vec![vec![
+            "borrow",
+            "borrowed",
+            "check",
+            "chuck",
+            "checker",
+            "action",
+            "title:borrow",
+        ]];
+        let vocabulary = Vocabulary::from_corpus(&corpus);
+        let matching = |pattern: &str| -> Vec<&str> {
+            vocabulary
+                .matching_terms(pattern)
+                .map(|(term, _)| term)
+                .collect()
+        };
+
+        assert_eq!(matching("borrow*"), vec!["borrow", "borrowed"]);
+        assert_eq!(matching("ch?ck"), vec!["check", "chuck"]);
+        assert_eq!(matching("*tion"), vec!["action"]);
+        assert_eq!(matching("c*e*"), vec!["check", "checker"]);
+        assert_eq!(matching("title:*"), vec!["title:borrow"]);
+        assert_eq!(matching("borrow"), vec!["borrow"]);
+        assert!(matching("x*").is_empty());
+    }
+
+    #[test]
+    fn test_similar_terms() {
+        let corpus: Vec<Vec<&str>> = vec![vec!["borrow", "barrow", "check", "chuck"]];
+        let mut vocabulary = Vocabulary::from_corpus(&corpus);
+        let similar = |vocabulary: &Vocabulary, word: &str| -> Vec<String> {
+            let mut terms: Vec<String> = vocabulary
+                .similar_terms(word, 1)
+                .into_iter()
+                .map(|(id, _)| vocabulary.term(id).to_string())
+                .collect();
+            terms.sort_unstable();
+            terms
+        };
+
+        // The tree is built on the first search, and kept up to date after that.
+        assert!(vocabulary.similar.get().is_none());
+        assert_eq!(similar(&vocabulary, "borow"), vec!["borrow"]);
+        assert!(vocabulary.similar.get().is_some());
+        vocabulary.get_or_insert("borrows");
+        assert_eq!(similar(&vocabulary, "borrowz"), vec!["borrow", "borrows"]);
+
+        // A term inserted before the first search is found too.
+        let mut unsearched = Vocabulary::from_corpus(&corpus);
+        unsearched.get_or_insert("borrows");
+        assert!(unsearched.similar.get().is_none());
+        assert_eq!(similar(&unsearched, "borrowz"), vec!["borrow", "borrows"]);
+    }
+
+    #[test]
+    fn test_ids_are_stable() {
+        let corpus: Vec<Vec<String>> = vec![
+            vec!["sky".to_string(), "blue".to_string()],
+            vec!["sun".to_string(), "sky".to_string()],
+        ];
+
+        assert_eq!(
+            Vocabulary::from_corpus(&corpus),
+            Vocabulary::from_corpus(&corpus)
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use super::loading;
+use super::model::Model;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, PoisonError, RwLock};
+use std::thread;
+use std::time::Duration;
+
+/// How long to wait for further events before applying a batch of changes, so that e.g. an editor
+/// writing a file in several steps only causes one update.
+const SETTLE_TIME: Duration = Duration::from_millis(200);
+
+/// Keep a model up to date with the files below a directory until the returned watcher is
+/// dropped.
+///
+/// File system events are collected on a background thread and applied to the model in batches
+/// (see `loading::update_documents`), so readers of the model always see the state of the
+/// directory as of the last batch.
+pub fn watch_directory(
+    model: Arc<RwLock<Model>>,
+    dir_name: &str,
+) -> Result<RecommendedWatcher, String> {
+    let root: PathBuf =
+        fs::canonicalize(dir_name).map_err(|err| format!("Could not watch {dir_name}: {err}"))?;
+
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)
+        .map_err(|err| format!("Could not watch {dir_name}: {err}"))?;
+    watcher
+        .watch(&root, RecursiveMode::Recursive)
+        .map_err(|err| format!("Could not watch {dir_name}: {err}"))?;
+
+    let dir_name = dir_name.to_string();
+    thread::spawn(move || apply_events(&model, &receiver, &root, &dir_name));
+
+    Ok(watcher)
+}
+
+/// Apply batches of events to the model until the watcher is dropped.
+fn apply_events(
+    model: &RwLock<Model>,
+    receiver: &Receiver<notify::Result<Event>>,
+    root: &Path,
+    dir_name: &str,
+) {
+    // Block until something happens, then gather everything that follows shortly after.
+    while let Ok(first) = receiver.recv() {
+        let mut changed: BTreeSet<PathBuf> = BTreeSet::new();
+        let mut next = Some(first);
+
+        while let Some(event) = next {
+            match event {
+                Ok(event) if !matches!(event.kind, EventKind::Access(_)) => {
+                    changed.extend(event.paths);
+                }
+                Ok(_) => {}
+                Err(err) => println!("Error watching files: {err}"),
+            }
+            next = receiver.recv_timeout(SETTLE_TIME).ok();
+        }
+
+        let mut model = model.write().unwrap_or_else(PoisonError::into_inner);
+        let paths = affected_paths(&model, &changed, root, dir_name);
+        let summary = loading::update_documents(&mut model, &paths);
+
+        if !summary.is_unchanged() {
+            println!(
+                "(Index updated: {} added, {} modified, {} removed.)",
+                summary.added, summary.modified, summary.removed
+            );
+        }
+    }
+}
+
+/// Work out which document paths need checking after changes to a set of (absolute) paths.
+///
+/// Event paths are rewritten relative to `dir_name` so that they match the paths in the document
+/// table. A changed directory (e.g. one which was moved or deleted) affects every file below it.
+fn affected_paths(
+    model: &Model,
+    changed: &BTreeSet<PathBuf>,
+    root: &Path,
+    dir_name: &str,
+) -> Vec<String> {
+    let mut paths: BTreeSet<String> = BTreeSet::new();
+
+    for path in changed {
+        let Ok(relative) = path.strip_prefix(root) else {
+            continue;
+        };
+        let local: PathBuf = Path::new(dir_name).join(relative);
+        let local_name: String = local.to_string_lossy().to_string();
+
+        if local.is_dir() {
+            paths.extend(loading::walk_directory(&local_name));
+        } else if loading::is_text_file(&local) {
+            paths.insert(local_name);
+        }
+
+        // Anything the model holds at or below the changed path.
+        paths.extend(
+            model
+                .documents
+                .iter()
+                .filter(|doc| Path::new(&doc.path).starts_with(&local))
+                .map(|doc| doc.path.clone()),
+        );
+    }
+
+    paths.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::DocumentInfo;
+    use crate::text_process::{tokens_from_words, Token};
+
+    #[test]
+    fn test_affected_paths() {
+        let dir = std::env::temp_dir().join(format!("sesquioxide-watch-{}", std::process::id()));
+        fs::create_dir_all(dir.join("notes")).unwrap();
+        fs::write(dir.join("notes/new.md"), "sky").unwrap();
+        let root = fs::canonicalize(&dir).unwrap();
+
+        let documents: Vec<DocumentInfo> = ["rel/old/a.txt", "rel/old/b.txt", "rel/c.txt"]
+            .iter()
+            .map(|path| DocumentInfo {
+                path: path.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        let corpus: Vec<Vec<Token>> = vec![tokens_from_words(&["sky".to_string()]); 3];
+        let model = Model::new(documents, &corpus);
+
+        // A directory moved away, a new directory, a file and something which is not a note.
+        let changed: BTreeSet<PathBuf> = [
+            root.join("old"),
+            root.join("notes"),
+            root.join("c.txt"),
+            root.join(".sesquioxide.idx"),
+        ]
+        .into_iter()
+        .collect();
+
+        let result = affected_paths(&model, &changed, &root, "rel");
+
+        // "rel" does not exist, so "rel/notes" is not found as a directory and adds no files.
+        assert_eq!(
+            result,
+            vec![
+                "rel/c.txt".to_string(),
+                "rel/old/a.txt".to_string(),
+                "rel/old/b.txt".to_string(),
+            ]
+        );
+
+        let dir_name = dir.to_string_lossy().to_string();
+        let result = affected_paths(&model, &changed, &root, &dir_name);
+
+        assert!(result.contains(&dir.join("notes/new.md").to_string_lossy().to_string()));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}